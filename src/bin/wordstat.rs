@@ -0,0 +1,326 @@
+//! The `wordstat` command-line tool.
+//!
+//! Reads the token from `--token`, the `WORDSTAT_TOKEN` environment variable or the
+//! `token` field of the JSON config file (`$XDG_CONFIG_HOME/wordstat/config.json` or
+//! `~/.config/wordstat/config.json` by default):
+//! ```text
+//! {"token": "...", "url": "https://api-sandbox.direct.yandex.ru/v4/json/"}
+//! ```
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use wordstat_rs::*;
+
+const DEFAULT_URL: &str = "https://api.direct.yandex.ru/v4/json/";
+
+#[derive(Parser)]
+#[command(name = "wordstat", version, about = "Query Yandex Wordstat from the command line")]
+struct Cli {
+    /// The API token
+    #[arg(long, env = "WORDSTAT_TOKEN", hide_env_values = true, global = true)]
+    token: Option<String>,
+    /// The API URL
+    #[arg(long, env = "WORDSTAT_URL", global = true)]
+    url: Option<String>,
+    /// The JSON config file with the token and the URL
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// The output format
+    #[arg(long, value_enum, default_value_t = Format::Table, global = true)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the available regions
+    Regions {
+        /// Only print the regions matching the name
+        #[arg(long)]
+        name: Option<String>
+    },
+    /// Create a report and print its ID
+    Create(ReportArgs),
+    /// Print the reports stored on the server and their statuses
+    List,
+    /// Print a generated report
+    Get {
+        report_id: i64
+    },
+    /// Delete a report from the server
+    Delete {
+        report_id: i64
+    },
+    /// Create a report, wait for it, print it and delete it from the server
+    Run {
+        #[command(flatten)]
+        report: ReportArgs,
+        /// Seconds between report status checks
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+        /// Give up after this many seconds
+        #[arg(long)]
+        timeout: Option<u64>
+    }
+}
+
+#[derive(clap::Args)]
+struct ReportArgs {
+    /// The phrases, up to 10
+    #[arg(required = true)]
+    phrases: Vec<String>,
+    /// Region IDs or names separated by commas, prefix with '-' to exclude a region
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    geo: Vec<String>
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+    Csv
+}
+
+#[derive(Default, Deserialize)]
+struct Config {
+    token: Option<String>,
+    url: Option<String>
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => { ExitCode::SUCCESS }
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config(cli.config.as_ref())?;
+    let token = cli.token.or(config.token)
+        .ok_or("the token is not set, pass --token, set WORDSTAT_TOKEN or add it to the config file")?;
+    let url = cli.url.or(config.url).unwrap_or_else(|| DEFAULT_URL.to_string());
+    let client = Client::new(&token, &url);
+    let format = cli.format;
+
+    match cli.command {
+        Command::Regions { name } => {
+            let mut regions = get_regions(&client).await?;
+            if let Some(name) = name {
+                let resolver = RegionResolver::new(&regions);
+                regions = resolver.resolve(&name).into_iter().map(|found| found.region.clone()).collect();
+            }
+            let rows = regions.iter()
+                .map(|region| vec![region.id.to_string(), optional(region.parent_id), region.name.clone()])
+                .collect();
+            print_rows(format, &regions, &["id", "parent_id", "name"], rows)?;
+        }
+        Command::Create(report) => {
+            let request = build_request(&client, &report).await?;
+            let report_id = create_report(&client, &request).await?;
+            print_rows(format, &report_id, &["report_id"], vec![vec![report_id.to_string()]])?;
+        }
+        Command::List => {
+            let statuses = get_report_list(&client).await?;
+            let rows = statuses.iter()
+                .map(|status| vec![status.report_id.to_string(), format!("{:?}", status.status)])
+                .collect();
+            print_rows(format, &statuses, &["report_id", "status"], rows)?;
+        }
+        Command::Get { report_id } => {
+            print_entries(format, &get_report(&client, report_id).await?)?;
+        }
+        Command::Delete { report_id } => {
+            delete_report(&client, report_id).await?;
+        }
+        Command::Run { report, interval, timeout } => {
+            let request = build_request(&client, &report).await?;
+            let report_id = create_report(&client, &request).await?;
+            let mut options = PollOptions::new().with_interval(Duration::from_secs(interval));
+            if let Some(timeout) = timeout { options = options.with_timeout(Duration::from_secs(timeout)); }
+            let result = wait_for_report(&client, report_id, options).await;
+            // Free the slot on the server even if waiting failed
+            let deleted = delete_report(&client, report_id).await;
+            print_entries(format, &result?)?;
+            deleted?;
+        }
+    }
+    Ok(())
+}
+
+fn load_config(path: Option<&PathBuf>) -> Result<Config, Box<dyn std::error::Error>> {
+    let (path, required) = match path {
+        Some(path) => { (path.clone(), true) }
+        None => {
+            let directory = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+            let Some(directory) = directory else { return Ok(Config::default()) };
+            (directory.join("wordstat").join("config.json"), false)
+        }
+    };
+    match std::fs::read(&path) {
+        Ok(contents) => { Ok(serde_json::from_slice(&contents).map_err(|error| format!("bad config {}: {error}", path.display()))?) }
+        Err(error) if !required && error.kind() == std::io::ErrorKind::NotFound => { Ok(Config::default()) }
+        Err(error) => { Err(format!("can't read config {}: {error}", path.display()).into()) }
+    }
+}
+
+async fn build_request(client: &Client, report: &ReportArgs) -> Result<ReportRequest, Box<dyn std::error::Error>> {
+    let mut request = ReportRequest::new();
+    for phrase in &report.phrases {
+        request = request.add_phrase(phrase)?;
+    }
+    if report.geo.is_empty() { return Ok(request); }
+
+    let regions = if report.geo.iter().all(|geo| geo.trim().parse::<i64>().is_ok()) { vec![] } else { get_regions(client).await? };
+    Ok(request.with_geo(&parse_geo(&report.geo, &regions)?))
+}
+
+/// Turns region IDs and names into IDs, negative for the ones starting with '-'.
+/// A name must match a single region exactly, up to case or transliteration.
+fn parse_geo(geo: &[String], regions: &[Region]) -> Result<Vec<i64>, String> {
+    let resolver = RegionResolver::new(regions);
+    geo.iter()
+        .map(|value| {
+            let value = value.trim();
+            if let Ok(id) = value.parse::<i64>() { return Ok(id); }
+            let (name, sign) = match value.strip_prefix('-') {
+                Some(name) => { (name, -1) }
+                None => { (value, 1) }
+            };
+            resolve_region(&resolver, name).map(|id| id * sign)
+        })
+        .collect()
+}
+
+fn resolve_region(resolver: &RegionResolver, name: &str) -> Result<i64, String> {
+    let matches = resolver.resolve(name);
+    let Some(best) = matches.first() else { return Err(format!("unknown region '{name}'")) };
+    // Prefix and fuzzy matches are only suggestions
+    let reliable = best.kind <= MatchKind::Transliterated;
+    let candidates: Vec<&RegionMatch> = match reliable {
+        true => { matches.iter().filter(|found| found.kind == best.kind).collect() }
+        false => { matches.iter().take(5).collect() }
+    };
+    if reliable && candidates.len() == 1 { return Ok(best.region.id); }
+
+    let reason = if reliable { "is ambiguous" } else { "has no exact match" };
+    let candidates: Vec<String> = candidates.iter()
+        .map(|found| format!("{} ({})", found.region.name, found.region.id))
+        .collect();
+    Err(format!("region '{name}' {reason}, candidates: {}", candidates.join(", ")))
+}
+
+fn print_entries(format: Format, entries: &[ReportEntry]) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        Format::Csv => { write_csv(std::io::stdout().lock(), entries, &CsvOptions::new())?; }
+        _ => {
+            let rows = entries.iter()
+                .flat_map(|entry| {
+                    entry.searched_with.iter().map(|item| ("searched_with", item))
+                        .chain(entry.searched_also.iter().map(|item| ("searched_also", item)))
+                        .map(|(relation, item)| vec![entry.phrase.clone(), relation.to_string(), item.phrase.clone(), item.shows.to_string()])
+                })
+                .collect();
+            print_rows(format, &entries, &["phrase", "relation", "item_phrase", "shows"], rows)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the value as JSON or the rows as a table or CSV
+fn print_rows<T: Serialize>(format: Format, value: &T, header: &[&str], rows: Vec<Vec<String>>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdout = std::io::stdout().lock();
+    match format {
+        Format::Json => { writeln!(stdout, "{}", serde_json::to_string_pretty(value)?)?; }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(stdout);
+            writer.write_record(header)?;
+            for row in rows { writer.write_record(row)?; }
+            writer.flush()?;
+        }
+        Format::Table => { write!(stdout, "{}", table(header, &rows))?; }
+    }
+    Ok(())
+}
+
+/// Lays the rows out in columns padded to the widest cell
+fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = header.iter().map(|cell| cell.to_string()).collect();
+    let mut output = String::new();
+    for row in std::iter::once(&header).chain(rows) {
+        let cells: Vec<String> = row.iter().zip(&widths)
+            .map(|(cell, width)| format!("{cell}{}", " ".repeat(width - cell.chars().count())))
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+fn optional(value: Option<i64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geo_by_id_and_name() {
+        let regions = vec![
+            Region { name: "Москва".to_string(), id: 213, parent_id: Some(1) },
+            Region { name: "Санкт-Петербург".to_string(), id: 2, parent_id: Some(10174) },
+        ];
+        let geo: Vec<String> = vec!["225".to_string(), "moskva".to_string(), "-Санкт-Петербург".to_string(), "-1".to_string()];
+
+
+        let received = parse_geo(&geo, &regions).unwrap();
+
+
+        assert_eq!(received, vec![225, 213, -2, -1]);
+        assert!(parse_geo(&["Vladivostok".to_string()], &regions).is_err())
+    }
+
+    #[test]
+    fn geo_requires_single_exact_match() {
+        let regions = vec![
+            Region { name: "Москва".to_string(), id: 213, parent_id: Some(1) },
+            Region { name: "Киров".to_string(), id: 46, parent_id: Some(11070) },
+            Region { name: "Киров".to_string(), id: 20165, parent_id: Some(10650) },
+        ];
+
+
+        let received = parse_geo(&["Моск".to_string()], &regions);
+
+
+        assert_eq!(received, Err("region 'Моск' has no exact match, candidates: Москва (213)".to_string()));
+        assert_eq!(parse_geo(&["Kirov".to_string()], &regions), Err("region 'Kirov' is ambiguous, candidates: Киров (46), Киров (20165)".to_string()))
+    }
+
+    #[test]
+    fn table_layout() {
+        let rows = vec![vec!["213".to_string(), "Москва".to_string()], vec!["2".to_string(), "Санкт-Петербург".to_string()]];
+
+
+        let received = table(&["id", "name"], &rows);
+
+
+        assert_eq!(received, "id   name\n213  Москва\n2    Санкт-Петербург\n")
+    }
+}
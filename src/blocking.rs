@@ -0,0 +1,274 @@
+//! Synchronous versions of the API functions for programs without an async runtime.
+//!
+//! They send the same requests and parse the responses with the same code as their
+//! async counterparts, but block the current thread until the response arrives.
+//! ```rust,ignore
+//! use wordstat_rs::blocking;
+//!
+//! let client = blocking::Client::new("token", "api_url");
+//! let regions = blocking::get_regions(&client)?;
+//! ```
+//! Like [reqwest::blocking], these functions must not be called from within an async runtime.
+use std::time::{Duration, Instant};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::{WordstatError, check_status};
+use crate::client::build_payload;
+use crate::create_report::{self, ReportRequest};
+use crate::delete_report::{self};
+use crate::get_report::{self, ReportEntry, ReportEntryModel};
+use crate::region::{self, Region, RegionModel};
+use crate::report_list::{self, ReportStatus, ReportStatusModel};
+use crate::response::{bad_response, parse_response};
+use crate::retry::RetryPolicy;
+use crate::transport::{TransportErrorKind, check_http_status, read_body, transport_error};
+use crate::wait_for_report::PollOptions;
+
+/// Blocking Yandex Direct API client.
+/// Stores the token, API URL, the [reqwest::blocking::Client] used to send requests and
+/// the [RetryPolicy] applied to them
+pub struct Client {
+    token: String,
+    api_url: String,
+    client: reqwest::blocking::Client,
+    retry_policy: RetryPolicy
+}
+
+impl Client {
+    /// Creates a new blocking Yandex Direct API client
+    pub fn new(token: &str, api_url: &str) -> Self {
+        Client::with_client(token, api_url, reqwest::blocking::Client::new())
+    }
+
+    /// Creates a new blocking client sending requests through an already configured reqwest client
+    pub fn with_client(token: &str, api_url: &str, client: reqwest::blocking::Client) -> Self {
+        Client {
+            token: token.to_string(),
+            api_url: api_url.to_string(),
+            client,
+            retry_policy: RetryPolicy::none()
+        }
+    }
+
+    /// Assigns the passed value as the client's token.
+    pub fn set_token(&mut self, token: &str) {
+        self.token = token.to_string();
+    }
+
+    /// Assigns the passed value as the client's API URL.
+    pub fn set_url(&mut self, api_url: &str) {
+        self.api_url = api_url.to_string();
+    }
+
+    /// Assigns the passed value as the client's [RetryPolicy].
+    /// By default failed requests are not retried.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    #[doc(hidden)]
+    pub fn post(&self, method: &str, params: Option<Value>) -> Result<Value, WordstatError> {
+        self.send(method, params, |response| {
+            let response = serde_json::from_slice(response)
+                .map_err(|error| bad_response(".", &error.to_string()))?;
+            check_status(&response).map(|_| response)
+        })
+    }
+
+    /// Sends the request and deserializes the data of the response
+    pub(crate) fn call<T: DeserializeOwned>(&self, method: &str, params: Option<Value>) -> Result<T, WordstatError> {
+        self.send(method, params, parse_response)
+    }
+
+    fn send<T>(&self, method: &str, params: Option<Value>, parse: impl Fn(&[u8]) -> Result<T, WordstatError>) -> Result<T, WordstatError> {
+        let payload = build_payload(method, &self.token, params);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = self.post_bytes(&payload)
+                .and_then(|response| parse(&response));
+            match result {
+                Err(error) if self.retry_policy.should_retry(method, attempt, &error) => {
+                    std::thread::sleep(self.retry_policy.delay(attempt));
+                }
+                result => { return result; }
+            }
+        }
+    }
+
+    fn post_bytes(&self, payload: &Value) -> Result<Vec<u8>, WordstatError> {
+        let response = self.client.post(&self.api_url)
+            .json(payload)
+            .send()
+            .map_err(|error| transport_error(error, TransportErrorKind::Request))?;
+        check_http_status(response.status())?;
+
+        read_body(response.bytes())
+    }
+}
+
+/// Blocking version of [create_report](crate::create_report::create_report)
+pub fn create_report(client: &Client, request: &ReportRequest) -> Result<i64, WordstatError> {
+    client.call(create_report::METHOD, Some(create_report::request_params(request)))
+}
+
+/// Blocking version of [get_report_list](crate::report_list::get_report_list)
+pub fn get_report_list(client: &Client) -> Result<Vec<ReportStatus>, WordstatError> {
+    let reports: Vec<ReportStatusModel> = client.call(report_list::METHOD, None)?;
+
+    Ok(reports.into_iter().map(ReportStatus::from).collect())
+}
+
+/// Blocking version of [get_report](crate::get_report::get_report)
+pub fn get_report(client: &Client, report_id: i64) -> Result<Vec<ReportEntry>, WordstatError> {
+    let report: Vec<ReportEntryModel> = client.call(get_report::METHOD, Some(Value::from(report_id)))?;
+
+    Ok(report.into_iter().map(ReportEntry::from).collect())
+}
+
+/// Blocking version of [delete_report](crate::delete_report::delete_report)
+pub fn delete_report(client: &Client, report_id: i64) -> Result<(), WordstatError> {
+    let return_code: i64 = client.call(delete_report::METHOD, Some(Value::from(report_id)))?;
+
+    delete_report::check_delete_result(return_code)
+}
+
+/// Blocking version of [get_regions](crate::region::get_regions)
+pub fn get_regions(client: &Client) -> Result<Vec<Region>, WordstatError> {
+    let regions: Vec<RegionModel> = client.call(region::METHOD, None)?;
+
+    Ok(regions.into_iter().map(Region::from).collect())
+}
+
+/// Blocking version of [wait_for_report](crate::wait_for_report::wait_for_report).
+///
+/// Follows the interval, backoff and timeout of the [PollOptions]. Cancellation is checked
+/// before every request and while sleeping, but does not interrupt a request in flight.
+pub fn wait_for_report(client: &Client, report_id: i64, options: PollOptions) -> Result<Vec<ReportEntry>, WordstatError> {
+    let started = Instant::now();
+    let mut interval = options.first_interval();
+
+    loop {
+        if options.is_cancelled() { return Err(WordstatError::Cancelled); }
+
+        let statuses = get_report_list(client)?;
+        let Some(status) = statuses.iter().find(|status| status.report_id == report_id) else { return Err(WordstatError::ReportNotFound { report_id }) };
+        match status.status {
+            report_list::StatusCode::Done => {
+                if options.is_cancelled() { return Err(WordstatError::Cancelled); }
+                match get_report(client, report_id) {
+                    Err(WordstatError::ReportNotReady { .. }) => {}
+                    result => { return result; }
+                }
+            }
+            report_list::StatusCode::Failed => { return Err(WordstatError::ReportFailed { report_id }); }
+            _ => {}
+        }
+
+        let delay = options.delay(interval, started.elapsed(), report_id)?;
+        sleep(delay, &options)?;
+        interval = options.next_interval(interval);
+    }
+}
+
+/// How often a sleeping [wait_for_report] checks the cancellation token
+const CANCELLATION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sleeps for the passed time, waking up early if waiting is cancelled
+fn sleep(delay: Duration, options: &PollOptions) -> Result<(), WordstatError> {
+    let deadline = Instant::now() + delay;
+    loop {
+        if options.is_cancelled() { return Err(WordstatError::Cancelled); }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() { return Ok(()); }
+        std::thread::sleep(left.min(CANCELLATION_CHECK_INTERVAL));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::MockServer;
+
+    #[test]
+    fn report_lifecycle() {
+        let server = MockServer::start()
+            .with_script(vec![report_list::StatusCode::Pending, report_list::StatusCode::Done]);
+        let client = Client::new(&server.token(), &server.url());
+        let request = ReportRequest::new().add_phrase("rust").unwrap().add_geo(213);
+
+
+        let report_id = create_report(&client, &request).unwrap();
+        let statuses = get_report_list(&client).unwrap();
+        let report = wait_for_report(&client, report_id, PollOptions::new().with_interval(Duration::from_millis(1))).unwrap();
+        delete_report(&client, report_id).unwrap();
+
+
+        assert_eq!(statuses[0].status, report_list::StatusCode::Pending);
+        assert_eq!(report[0].phrase, "rust");
+        assert!(get_report_list(&client).unwrap().is_empty())
+    }
+
+    #[test]
+    fn regions() {
+        let server = MockServer::start();
+        let client = Client::new(&server.token(), &server.url());
+
+
+        let received = get_regions(&client).unwrap();
+
+
+        assert_eq!(received.len(), 5)
+    }
+
+    #[test]
+    fn api_error() {
+        let server = MockServer::start();
+        let client = Client::new("wrong", &server.url());
+
+
+        let received = get_report(&client, 1);
+
+
+        assert!(matches!(received, Err(WordstatError::AuthorizationError { .. })))
+    }
+
+    #[test]
+    fn wait_timeout() {
+        let server = MockServer::start().with_script(vec![report_list::StatusCode::Pending]);
+        let client = Client::new(&server.token(), &server.url());
+        let report_id = create_report(&client, &ReportRequest::new().add_phrase("rust").unwrap()).unwrap();
+        let options = PollOptions::new()
+            .with_interval(Duration::from_millis(5))
+            .with_timeout(Duration::from_millis(50));
+
+
+        let received = wait_for_report(&client, report_id, options);
+
+
+        assert!(matches!(received, Err(WordstatError::DeadlineExceeded { .. })))
+    }
+
+    #[test]
+    fn wait_cancelled() {
+        let server = MockServer::start().with_script(vec![report_list::StatusCode::Pending]);
+        let client = Client::new(&server.token(), &server.url());
+        let report_id = create_report(&client, &ReportRequest::new().add_phrase("rust").unwrap()).unwrap();
+        let token = tokio_util::sync::CancellationToken::new();
+        let canceller = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let options = PollOptions::new()
+            .with_interval(Duration::from_secs(60))
+            .with_cancellation(token);
+
+
+        let received = wait_for_report(&client, report_id, options);
+
+
+        assert!(matches!(received, Err(WordstatError::Cancelled)))
+    }
+}
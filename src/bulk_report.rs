@@ -0,0 +1,290 @@
+use std::sync::Arc;
+use futures::StreamExt;
+use crate::{ApiError, WordstatError};
+use crate::create_report::{ReportRequest, MAX_PHRASES};
+use crate::get_report::ReportEntry;
+use crate::report_manager::ReportManager;
+
+/// The result for a single phrase of a [BulkReportRequest]
+#[derive(Debug)]
+pub struct PhraseReport {
+    /// The phrase as it was passed to the request
+    pub phrase: String,
+    /// The report entry for the phrase or the error that prevented getting it.
+    /// The error is shared by all phrases that were sent in the same report.
+    pub result: Result<ReportEntry, Arc<WordstatError>>
+}
+
+/// A request for any number of phrases. The phrases are split into
+/// [ReportRequest]s of up to 10 phrases, which are run through a [ReportManager].
+///
+/// ```rust,ignore
+/// let results = BulkReportRequest::new()
+///     .with_phrases(&phrases)
+///     .with_geo(&[213])
+///     .run(ReportManager::new(&client))
+///     .await?;
+/// for report in results {
+///     println!("{}: {:?}", report.phrase, report.result);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BulkReportRequest {
+    phrases: Vec<String>,
+    geo_id: Vec<i64>
+}
+
+impl BulkReportRequest {
+    /// Create a new BulkReportRequest object
+    pub fn new() -> Self {
+        BulkReportRequest { phrases: vec![], geo_id: vec![] }
+    }
+    /// Add a phrase to the request. Invalid phrases are not rejected here,
+    /// they get their error in the [PhraseReport] instead.
+    pub fn add_phrase(mut self, phrase: &str) -> Self {
+        self.phrases.push(phrase.to_string());
+        self
+    }
+    /// Same as [add_phrase](BulkReportRequest::add_phrase) but takes several phrases at once.
+    pub fn with_phrases(mut self, phrases: &[&str]) -> Self {
+        for phrase in phrases {
+            self = self.add_phrase(phrase);
+        }
+        self
+    }
+    /// Add region ID to be used for every phrase.
+    pub fn add_geo(mut self, geo_id: i64) -> Self {
+        self.geo_id.push(geo_id);
+        self
+    }
+    /// Same as [add_geo](BulkReportRequest::add_geo) but takes a vector of items instead of
+    /// a single one.
+    pub fn with_geo(mut self, geo_ids: &[i64]) -> Self {
+        self.geo_id = geo_ids.to_vec();
+        self
+    }
+
+    /// Splits the phrases into [ReportRequest]s of up to 10 phrases.
+    /// Returns the chunks and the errors for phrases that could not be added to any request.
+    fn split(&self) -> (Vec<Chunk>, Vec<(usize, WordstatError)>) {
+        let mut chunks = vec![];
+        let mut invalid = vec![];
+        let mut request = ReportRequest::new().with_geo(&self.geo_id);
+        let mut positions = vec![];
+
+        for (position, phrase) in self.phrases.iter().enumerate() {
+            if positions.len() == MAX_PHRASES {
+                chunks.push(Chunk { request, positions });
+                request = ReportRequest::new().with_geo(&self.geo_id);
+                positions = vec![];
+            }
+            match request.clone().add_phrase(phrase) {
+                Ok(extended) => {
+                    request = extended;
+                    positions.push(position);
+                }
+                Err(error) => { invalid.push((position, error)); }
+            }
+        }
+        if !positions.is_empty() {
+            chunks.push(Chunk { request, positions });
+        }
+
+        (chunks, invalid)
+    }
+
+    /// Runs the request through the passed manager and returns one [PhraseReport]
+    /// per phrase, in the order the phrases were added.
+    ///
+    /// Returns [ManagerNotEmpty](WordstatError::ManagerNotEmpty) if requests were already
+    /// added to the manager, as their results would be lost.
+    pub async fn run(self, mut manager: ReportManager<'_>) -> Result<Vec<PhraseReport>, WordstatError> {
+        let requests = manager.request_count();
+        if requests != 0 { return Err(WordstatError::ManagerNotEmpty { requests }); }
+        let (chunks, invalid) = self.split();
+        let mut results: Vec<Option<Result<ReportEntry, Arc<WordstatError>>>> =
+            self.phrases.iter().map(|_| None).collect();
+        for (position, error) in invalid {
+            results[position] = Some(Err(Arc::new(error)));
+        }
+
+        let mut submitted = vec![];
+        for chunk in chunks {
+            submitted.push(chunk.request.phrases().iter().cloned().zip(chunk.positions).collect::<Vec<(String, usize)>>());
+            manager = manager.add_request(chunk.request);
+        }
+
+        let mut outcomes = Box::pin(manager.run());
+        while let Some(outcome) = outcomes.next().await {
+            let outcome = match outcome {
+                Ok(outcome) => { outcome }
+                Err(error) => {
+                    // The manager has stopped, the remaining phrases share its error
+                    let error = Arc::new(error);
+                    for result in results.iter_mut().filter(|result| result.is_none()) {
+                        *result = Some(Err(error.clone()));
+                    }
+                    break;
+                }
+            };
+            let chunk = &submitted[outcome.index];
+            match outcome.result {
+                Ok(entries) => {
+                    // Phrases without an entry get the missing report error below
+                    for (phrase, position) in chunk {
+                        let Some(entry) = entries.iter().find(|entry| entry.phrase == *phrase) else { continue };
+                        results[*position] = Some(Ok(entry.clone()));
+                    }
+                }
+                Err(error) => {
+                    let error = Arc::new(error);
+                    for (_, position) in chunk {
+                        results[*position] = Some(Err(error.clone()));
+                    }
+                }
+            }
+        }
+
+        Ok(self.phrases.into_iter()
+            .zip(results)
+            .map(|(phrase, result)| PhraseReport {
+                phrase,
+                result: result.unwrap_or_else(|| Err(Arc::new(missing_report())))
+            })
+            .collect())
+    }
+}
+
+/// The error of a phrase the manager has returned no result for, same as the API
+/// returns for a report that does not exist
+fn missing_report() -> WordstatError {
+    let error = ApiError { code: 24, message: "Report does not exist".to_string(), detail: String::new() };
+    WordstatError::ReportDoesNotExist { error }
+}
+
+/// A request for up to 10 phrases and the positions of its phrases in the [BulkReportRequest]
+struct Chunk {
+    request: ReportRequest,
+    positions: Vec<usize>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use serde_json::{Value, json};
+    use crate::client::Client;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+
+    /// A client for a server that finishes every report right away,
+    /// listing the entries in reverse order if `reversed` is set
+    fn client(reversed: bool) -> Client {
+        let reports = Arc::new(Mutex::new(Vec::<(i64, Vec<Value>)>::new()));
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .returning(move |_url, payload| {
+                let mut reports = reports.lock().unwrap();
+                let param = payload.get("param").cloned().unwrap_or(Value::Null);
+                let response = match payload["method"].as_str().unwrap() {
+                    "CreateNewWordstatReport" => {
+                        let id = reports.len() as i64 + 1;
+                        reports.push((id, param["Phrases"].as_array().unwrap().clone()));
+                        json!({"data": id})
+                    }
+                    "GetWordstatReportList" => {
+                        let list: Vec<Value> = reports.iter()
+                            .map(|(id, _)| json!({"ReportID": id, "StatusReport": "Done"}))
+                            .collect();
+                        json!({"data": list})
+                    }
+                    "GetWordstatReport" => {
+                        let (_, phrases) = &reports[param.as_i64().unwrap() as usize - 1];
+                        let mut entries: Vec<Value> = phrases.iter()
+                            .map(|phrase| json!({"Phrase": phrase, "GeoID": [213], "SearchedWith": []}))
+                            .collect();
+                        if reversed { entries.reverse(); }
+                        json!({"data": entries})
+                    }
+                    "DeleteWordstatReport" => { json!({"data": 1}) }
+                    _ => { panic!("Unexpected method") }
+                };
+                Ok(response)
+            });
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_clock(ManualClock::new());
+        client
+    }
+
+    #[test]
+    fn split_phrases() {
+        let phrases: Vec<String> = (0..23).map(|i| format!("phrase {i}")).collect();
+        let mut request = BulkReportRequest::new().add_phrase("bad + phrase");
+        for phrase in &phrases {
+            request = request.add_phrase(phrase);
+        }
+
+
+        let (chunks, invalid) = request.split();
+
+
+        let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.positions.len()).collect();
+        assert_eq!(sizes, vec![10, 10, 3]);
+        assert_eq!(chunks[0].positions, (1..=10).collect::<Vec<usize>>());
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].0, 0)
+    }
+
+    #[test]
+    fn results_keep_phrase_order() {
+        let client = client(false);
+        let phrases: Vec<String> = (0..15).map(|i| format!("phrase {i}")).collect();
+        let mut request = BulkReportRequest::new().with_geo(&[213]);
+        for (i, phrase) in phrases.iter().enumerate() {
+            request = request.add_phrase(phrase);
+            if i == 4 { request = request.add_phrase("bad:phrase"); }
+        }
+
+
+        let received = futures::executor::block_on(request.run(ReportManager::new(&client))).unwrap();
+
+
+        assert_eq!(received.len(), 16);
+        assert!(matches!(received[5].result.as_ref().unwrap_err().as_ref(), WordstatError::BadKeyphrase { .. }));
+        let found: Vec<String> = received.iter()
+            .filter_map(|report| report.result.as_ref().ok())
+            .map(|entry| entry.phrase.clone())
+            .collect();
+        assert_eq!(found, phrases);
+        for report in received.iter().filter(|report| report.result.is_ok()) {
+            assert_eq!(report.result.as_ref().unwrap().phrase, report.phrase);
+        }
+    }
+
+    #[test]
+    fn matches_entries_by_phrase() {
+        let client = client(true);
+        let request = BulkReportRequest::new().with_phrases(&["rust", "cargo", "crate"]);
+
+
+        let received = futures::executor::block_on(request.run(ReportManager::new(&client))).unwrap();
+
+
+        for report in &received {
+            assert_eq!(report.result.as_ref().unwrap().phrase, report.phrase);
+        }
+        assert_eq!(received.len(), 3)
+    }
+
+    #[test]
+    fn rejects_used_manager() {
+        let client = Client::with_transport("token", "api_url", MockTransport::new());
+        let manager = ReportManager::new(&client).add_request(ReportRequest::new());
+
+
+        let received = futures::executor::block_on(BulkReportRequest::new().add_phrase("rust").run(manager));
+
+
+        assert!(matches!(received, Err(WordstatError::ManagerNotEmpty { requests: 1 })))
+    }
+}
@@ -1,16 +1,23 @@
-use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
-use crate::WordstatError;
+use crate::{WordstatError, check_status};
+use crate::response::{bad_response, parse_response};
+use crate::client_builder::ClientBuilder;
+use crate::clock::{Clock, TokioClock};
+use crate::retry::RetryPolicy;
+use crate::transport::{Transport, ReqwestTransport};
 
 /// Yandex Direct API client
-/// Stores the token and API URL
+/// Stores the token, API URL, the [Transport] used to send requests and
+/// the [RetryPolicy] applied to them
 pub struct Client {
     token: String,
     api_url: String,
-    client: reqwest::Client
+    transport: Box<dyn Transport>,
+    retry_policy: RetryPolicy,
+    clock: Box<dyn Clock>
 }
 
-#[cfg_attr(test, mockall::automock)]
 impl Client {
     /// Creates a new Yandex Direct API client
     /// ```
@@ -23,10 +30,24 @@ impl Client {
     /// If your token is for the API sandbox you should use <https://api-sandbox.direct.yandex.ru/v4/json/>
     /// as the URL.
     pub fn new(token: &str, api_url: &str) -> Self {
-        Client { 
+        Client::with_transport(token, api_url, ReqwestTransport::new())
+    }
+
+    /// Returns a [ClientBuilder] to configure timeouts, a proxy, headers or
+    /// a pre-built reqwest client.
+    pub fn builder(token: &str, api_url: &str) -> ClientBuilder {
+        ClientBuilder::new(token, api_url)
+    }
+
+    /// Creates a new Yandex Direct API client that sends requests through
+    /// the passed [Transport] instead of the default [ReqwestTransport].
+    pub fn with_transport<T: Transport + 'static>(token: &str, api_url: &str, transport: T) -> Self {
+        Client {
             token: token.to_string(),
             api_url: api_url.to_string(),
-            client: reqwest::Client::new()
+            transport: Box::new(transport),
+            retry_policy: RetryPolicy::none(),
+            clock: Box::new(TokioClock)
         }
     }
 
@@ -40,27 +61,66 @@ impl Client {
         self.api_url = api_url.to_string();
     }
 
+    /// Assigns the passed value as the client's [Transport].
+    pub fn set_transport<T: Transport + 'static>(&mut self, transport: T) {
+        self.transport = Box::new(transport);
+    }
+
+    /// Assigns the passed value as the client's [RetryPolicy].
+    /// By default failed requests are not retried.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Assigns the passed value as the client's [Clock], used for waiting between retries.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Returns the client's [Clock]
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
     #[doc(hidden)]
     pub async fn post(&self, method: &str, params: Option<Value>) -> Result<serde_json::Value, WordstatError> {
-        let mut payload = serde_json::Map::new();
-        payload.insert("method".to_string(), Value::from(method));
-        payload.insert("token".to_string(), Value::from(self.token.as_str()));
-        if let Some(param) = params {
-            payload.insert("param".to_string(), param);
-        }
+        self.send(method, params, |response| {
+            let response = serde_json::from_slice(response)
+                .map_err(|error| bad_response(".", &error.to_string()))?;
+            check_status(&response).map(|_| response)
+        }).await
+    }
 
-        let response = self.client.post(self.api_url.as_str())
-            .json(&payload)
-            .send()
-            .await.unwrap();
-        if response.status() != StatusCode::OK {
-            return Err(WordstatError::UnknownResponseCode { code: response.status().as_u16() as i64 });
-        }
+    /// Sends the request and deserializes the data of the response
+    pub(crate) async fn call<T: DeserializeOwned>(&self, method: &str, params: Option<Value>) -> Result<T, WordstatError> {
+        self.send(method, params, parse_response).await
+    }
+
+    async fn send<T>(&self, method: &str, params: Option<Value>, parse: impl Fn(&[u8]) -> Result<T, WordstatError>) -> Result<T, WordstatError> {
+        let payload = build_payload(method, &self.token, params);
 
-        let Ok(response_text) = response.text().await else { return Err(WordstatError::UnknownError) };
-        let Ok(response_json): Result<Value, serde_json::Error> = 
-                               serde_json::from_str(&response_text) else { return Err(WordstatError::BadResponse{ reason: "Failed to read JSON response" }) };
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let result = self.transport.post_bytes(self.api_url.as_str(), &payload).await
+                .and_then(|response| parse(&response));
+            match result {
+                Err(error) if self.retry_policy.should_retry(method, attempt, &error) => {
+                    self.clock.sleep(self.retry_policy.delay(attempt)).await;
+                }
+                result => { return result; }
+            }
+        }
+    }
+}
 
-        Ok(response_json)
+/// Builds the request body sent to the API
+pub(crate) fn build_payload(method: &str, token: &str, params: Option<Value>) -> Value {
+    let mut payload = serde_json::Map::new();
+    payload.insert("method".to_string(), Value::from(method));
+    payload.insert("token".to_string(), Value::from(token));
+    if let Some(param) = params {
+        payload.insert("param".to_string(), param);
     }
+    Value::Object(payload)
 }
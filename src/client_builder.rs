@@ -0,0 +1,229 @@
+use std::time::Duration;
+use reqwest::header::{ACCEPT_LANGUAGE, HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use crate::WordstatError;
+use crate::client::Client;
+use crate::retry::RetryPolicy;
+use crate::transport::ReqwestTransport;
+
+/// Configures the HTTP side of a [Client].
+///
+/// Connection timeout, proxy and compression are settings of the underlying
+/// [reqwest::Client], so they can not be combined with a
+/// [pre-built one](ClientBuilder::with_reqwest_client). Headers and the request
+/// timeout are applied to every request and work with both.
+/// ```
+/// # use wordstat_rs::*;
+/// # use std::time::Duration;
+/// let client = Client::builder("token", "https://api.direct.yandex.ru/v4/json/")
+///     .with_connect_timeout(Duration::from_secs(5))
+///     .with_timeout(Duration::from_secs(30))
+///     .with_proxy("socks5://127.0.0.1:1080")
+///     .with_user_agent("keyword-tool/1.0")
+///     .with_accept_language("en")
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ClientBuilder {
+    token: String,
+    api_url: String,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    proxy: Option<String>,
+    gzip: Option<bool>,
+    headers: Vec<(String, String)>,
+    user_agent: Option<String>,
+    accept_language: Option<String>,
+    reqwest_client: Option<reqwest::Client>,
+    retry_policy: RetryPolicy
+}
+
+impl ClientBuilder {
+    /// Creates a builder with the default settings of [Client::new]
+    pub fn new(token: &str, api_url: &str) -> Self {
+        ClientBuilder {
+            token: token.to_string(),
+            api_url: api_url.to_string(),
+            connect_timeout: None,
+            timeout: None,
+            proxy: None,
+            gzip: None,
+            headers: vec![],
+            user_agent: None,
+            accept_language: None,
+            reqwest_client: None,
+            retry_policy: RetryPolicy::none()
+        }
+    }
+
+    /// Sets the time limit for establishing a connection
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the time limit for a whole request, from connecting to reading the response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sends all requests through the proxy. Supports `http://`, `https://`,
+    /// `socks5://` and `socks5h://` URLs, credentials can be passed in the URL.
+    pub fn with_proxy(mut self, proxy_url: &str) -> Self {
+        self.proxy = Some(proxy_url.to_string());
+        self
+    }
+
+    /// Sets whether gzip compressed responses are requested and decompressed. Enabled by default.
+    pub fn with_gzip(mut self, gzip: bool) -> Self {
+        self.gzip = Some(gzip);
+        self
+    }
+
+    /// Adds a header sent with every request
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the `User-Agent` header
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Sets the `Accept-Language` header, which selects the language of the region
+    /// names returned by [get_regions](crate::region::get_regions), for example "en" or "ru".
+    pub fn with_accept_language(mut self, language: &str) -> Self {
+        self.accept_language = Some(language.to_string());
+        self
+    }
+
+    /// Sends requests through an already configured reqwest client, for example
+    /// to share its connection pool with the rest of the program.
+    pub fn with_reqwest_client(mut self, client: reqwest::Client) -> Self {
+        self.reqwest_client = Some(client);
+        self
+    }
+
+    /// Sets the [RetryPolicy] of the client
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Creates the client. Returns [InvalidClientConfig](WordstatError::InvalidClientConfig)
+    /// if a header or the proxy URL is invalid, or if client settings are combined with
+    /// a pre-built reqwest client.
+    pub fn build(self) -> Result<Client, WordstatError> {
+        let headers = self.headers()?;
+        let client = match self.reqwest_client {
+            Some(client) => {
+                if self.connect_timeout.is_some() || self.proxy.is_some() || self.gzip.is_some() {
+                    return Err(invalid_config("the connect timeout, proxy and gzip can't be set for a pre-built reqwest client"));
+                }
+                client
+            }
+            None => {
+                let mut builder = reqwest::Client::builder().gzip(self.gzip.unwrap_or(true));
+                if let Some(timeout) = self.connect_timeout { builder = builder.connect_timeout(timeout); }
+                if let Some(proxy) = &self.proxy {
+                    let proxy = reqwest::Proxy::all(proxy).map_err(|error| invalid_config(&format!("bad proxy: {error}")))?;
+                    builder = builder.proxy(proxy);
+                }
+                builder.build().map_err(|error| invalid_config(&error.to_string()))?
+            }
+        };
+
+        let transport = ReqwestTransport::with_client(client)
+            .with_headers(headers)
+            .with_timeout(self.timeout);
+        let mut client = Client::with_transport(&self.token, &self.api_url, transport);
+        client.set_retry_policy(self.retry_policy);
+        Ok(client)
+    }
+
+    fn headers(&self) -> Result<HeaderMap, WordstatError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| invalid_config(&format!("bad header name '{name}'")))?;
+            headers.append(name, header_value(value)?);
+        }
+        if let Some(user_agent) = &self.user_agent { headers.insert(USER_AGENT, header_value(user_agent)?); }
+        if let Some(language) = &self.accept_language { headers.insert(ACCEPT_LANGUAGE, header_value(language)?); }
+        Ok(headers)
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, WordstatError> {
+    HeaderValue::from_str(value).map_err(|_| invalid_config(&format!("bad header value '{value}'")))
+}
+
+fn invalid_config(reason: &str) -> WordstatError {
+    WordstatError::InvalidClientConfig { reason: reason.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::MockServer;
+    use crate::region::get_regions;
+
+    #[tokio::test]
+    async fn sends_headers() {
+        let server = MockServer::start();
+        let client = ClientBuilder::new(&server.token(), &server.url())
+            .with_timeout(Duration::from_secs(5))
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_user_agent("keyword-tool/1.0")
+            .with_accept_language("en")
+            .with_header("X-Request-Source", "tests")
+            .build()
+            .unwrap();
+
+
+        get_regions(&client).await.unwrap();
+
+
+        let headers = server.last_headers();
+        assert_eq!(headers.get("user-agent").unwrap(), "keyword-tool/1.0");
+        assert_eq!(headers.get("accept-language").unwrap(), "en");
+        assert_eq!(headers.get("x-request-source").unwrap(), "tests")
+    }
+
+    #[tokio::test]
+    async fn pre_built_client() {
+        let server = MockServer::start();
+        let client = ClientBuilder::new(&server.token(), &server.url())
+            .with_reqwest_client(reqwest::Client::new())
+            .with_accept_language("ru")
+            .build()
+            .unwrap();
+
+
+        get_regions(&client).await.unwrap();
+
+
+        assert_eq!(server.last_headers().get("accept-language").unwrap(), "ru")
+    }
+
+    #[test]
+    fn invalid_config() {
+        let conflicting = ClientBuilder::new("token", "api_url")
+            .with_reqwest_client(reqwest::Client::new())
+            .with_proxy("http://127.0.0.1:3128")
+            .build();
+        let bad_header = ClientBuilder::new("token", "api_url")
+            .with_header("bad header", "value")
+            .build();
+        let bad_proxy = ClientBuilder::new("token", "api_url")
+            .with_proxy("not a url")
+            .build();
+
+
+        assert!(matches!(conflicting, Err(WordstatError::InvalidClientConfig { .. })));
+        assert!(matches!(bad_header, Err(WordstatError::InvalidClientConfig { .. })));
+        assert!(matches!(bad_proxy, Err(WordstatError::InvalidClientConfig { .. })))
+    }
+}
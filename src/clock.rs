@@ -0,0 +1,104 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+
+/// Source of time used by the [Client](crate::client::Client) whenever it has to wait,
+/// for example between retries.
+///
+/// [TokioClock] is used by default. [ManualClock] can be used in tests to skip the
+/// waiting and inspect the requested delays.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    /// Returns the current moment
+    fn now(&self) -> Instant;
+    /// Waits for the passed duration
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Default [Clock] implementation backed by the tokio timer
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioClock;
+
+#[async_trait]
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// A fake [Clock] whose time only moves when somebody sleeps or
+/// [advances](ManualClock::advance) it. Sleeping returns immediately.
+///
+/// Clones share the same time, so a copy can be kept to inspect the clock
+/// after passing it to the client:
+/// ```
+/// # use wordstat_rs::*;
+/// let clock = ManualClock::new();
+/// let mut client = Client::new("token", "api_url");
+/// client.set_clock(clock.clone());
+/// // ...
+/// assert!(clock.sleeps().is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    state: Arc<Mutex<ManualClockState>>
+}
+
+#[derive(Debug)]
+struct ManualClockState {
+    start: Instant,
+    elapsed: Duration,
+    sleeps: Vec<Duration>
+}
+
+impl ManualClock {
+    /// Creates a new clock starting at the current moment
+    pub fn new() -> Self {
+        ManualClock {
+            state: Arc::new(Mutex::new(ManualClockState {
+                start: Instant::now(),
+                elapsed: Duration::ZERO,
+                sleeps: vec![]
+            }))
+        }
+    }
+
+    /// Moves the clock forward without recording a sleep
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().unwrap().elapsed += duration;
+    }
+
+    /// Returns the time passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+
+    /// Returns the durations of all sleeps, in order
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().sleeps.clone()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        let state = self.state.lock().unwrap();
+        state.start + state.elapsed
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed += duration;
+        state.sleeps.push(duration);
+    }
+}
@@ -0,0 +1,529 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::WordstatError;
+use crate::client::Client;
+use crate::create_report::{ReportRequest, MAX_PHRASES};
+use crate::delete_report::delete_report;
+use crate::file::write_atomically;
+use crate::get_report::ReportEntry;
+use crate::keyword_graph::{KeywordEdge, KeywordGraph, Relation};
+use crate::normalize::normalize_phrase;
+use crate::report_manager::ReportManager;
+
+/// The amount of phrases submitted at once, enough to fill the five reports the server stores
+const BATCH_SIZE: usize = MAX_PHRASES * 5;
+
+/// Discovers keywords by submitting the keywords found in reports as new reports, breadth-first.
+///
+/// The seed phrases have depth 0, the keywords found in their reports depth 1 and so on.
+/// Keywords deeper than the [maximum depth](KeywordCrawler::with_max_depth), with fewer
+/// shows than the [minimum](KeywordCrawler::with_min_shows) or seen before in any
+/// [normalized](crate::normalize::normalize_phrase) form are added to the graph but not submitted.
+///
+/// With a [state file](KeywordCrawler::with_state_file) the progress is saved after every
+/// batch of reports, and a crawl stopped by an error or a limit continues from where it stopped.
+/// Reports that could not be deleted after an error are saved as well and deleted on resume.
+/// ```rust,ignore
+/// let graph = KeywordCrawler::new(&client)
+///     .with_seeds(&["rust lang"])
+///     .with_max_depth(2)
+///     .with_min_shows(100)
+///     .with_max_reports(20)
+///     .with_state_file("crawl.json")
+///     .run()
+///     .await?;
+/// for edge in graph.edges() {
+///     println!("{} -> {} ({})", edge.source, edge.target, edge.relation.as_str());
+/// }
+/// ```
+pub struct KeywordCrawler<'a> {
+    client: &'a Client,
+    seeds: Vec<String>,
+    geo_id: Vec<i64>,
+    max_depth: u32,
+    max_phrases: usize,
+    min_shows: i64,
+    max_reports: Option<usize>,
+    state_file: Option<PathBuf>,
+    poll_interval: Duration
+}
+
+impl<'a> KeywordCrawler<'a> {
+    /// Creates a crawler with no seeds, submitting the keywords of the seeds only,
+    /// up to 100 phrases in total and polling every 10 seconds.
+    pub fn new(client: &'a Client) -> Self {
+        KeywordCrawler {
+            client,
+            seeds: vec![],
+            geo_id: vec![],
+            max_depth: 1,
+            max_phrases: 100,
+            min_shows: 0,
+            max_reports: None,
+            state_file: None,
+            poll_interval: Duration::from_secs(10)
+        }
+    }
+
+    /// Adds a phrase to start the crawl from
+    pub fn add_seed(mut self, phrase: &str) -> Self {
+        self.seeds.push(phrase.to_string());
+        self
+    }
+
+    /// Same as [add_seed](KeywordCrawler::add_seed) but takes several phrases at once
+    pub fn with_seeds(mut self, phrases: &[&str]) -> Self {
+        for phrase in phrases {
+            self = self.add_seed(phrase);
+        }
+        self
+    }
+
+    /// Sets the region IDs used for every report
+    pub fn with_geo(mut self, geo_ids: &[i64]) -> Self {
+        self.geo_id = geo_ids.to_vec();
+        self
+    }
+
+    /// Sets the depth of the deepest keywords that are submitted. 0 submits the seeds only.
+    pub fn with_max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Limits the amount of phrases submitted, seeds included
+    pub fn with_max_phrases(mut self, max_phrases: usize) -> Self {
+        self.max_phrases = max_phrases;
+        self
+    }
+
+    /// Sets the minimum amount of shows for a keyword to be submitted
+    pub fn with_min_shows(mut self, min_shows: i64) -> Self {
+        self.min_shows = min_shows;
+        self
+    }
+
+    /// Limits the amount of reports created, each holding up to 10 phrases.
+    /// Unlimited by default.
+    pub fn with_max_reports(mut self, max_reports: usize) -> Self {
+        self.max_reports = Some(max_reports);
+        self
+    }
+
+    /// Saves the progress to the file after every batch and continues from it if it exists.
+    /// Seeds not seen in the saved crawl are added to it.
+    pub fn with_state_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.state_file = Some(path.into());
+        self
+    }
+
+    /// Sets the time to wait between checking the report statuses
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Crawls until no phrases are left or a limit is reached and returns the graph
+    /// of every phrase seen. Returns [BadKeyphrase](WordstatError::BadKeyphrase) if a seed
+    /// is not valid, and the first error of a report after saving the progress.
+    pub async fn run(&self) -> Result<KeywordGraph, WordstatError> {
+        let mut state = match &self.state_file {
+            Some(path) => { CrawlState::load(path).await?.unwrap_or_default() }
+            None => { CrawlState::default() }
+        };
+        for seed in &self.seeds {
+            let key = normalize_phrase(seed)?;
+            if state.seen.insert(key) { state.queue.push_back(Queued { phrase: seed.clone(), depth: 0 }); }
+        }
+
+        loop {
+            self.delete_undeleted(&mut state).await;
+            let batch = self.next_batch(&mut state);
+            if batch.is_empty() { break; }
+            let result = self.crawl_batch(&mut state, batch).await;
+            self.save(&state).await?;
+            result?;
+        }
+        self.save(&state).await?;
+        Ok(state.graph)
+    }
+
+    /// Deletes the reports left on the server by earlier batches, keeping the ones
+    /// that still can not be deleted
+    async fn delete_undeleted(&self, state: &mut CrawlState) {
+        let mut undeleted = vec![];
+        for report_id in std::mem::take(&mut state.undeleted) {
+            match delete_report(self.client, report_id).await {
+                Ok(()) | Err(WordstatError::ReportDoesNotExist { .. }) => {}
+                Err(_) => { undeleted.push(report_id); }
+            }
+        }
+        state.undeleted = undeleted;
+    }
+
+    /// Takes as many phrases from the queue as the limits allow
+    fn next_batch(&self, state: &mut CrawlState) -> Vec<Queued> {
+        let mut size = BATCH_SIZE
+            .min(state.queue.len())
+            .min(self.max_phrases.saturating_sub(state.submitted));
+        if let Some(max_reports) = self.max_reports {
+            size = size.min(max_reports.saturating_sub(state.reports) * MAX_PHRASES);
+        }
+        state.queue.drain(..size).collect()
+    }
+
+    /// Runs the reports for the batch, adding the entries to the graph and the new keywords
+    /// to the queue. Phrases of failed reports are put back to the front of the queue.
+    async fn crawl_batch(&self, state: &mut CrawlState, batch: Vec<Queued>) -> Result<(), WordstatError> {
+        let chunks: Vec<&[Queued]> = batch.chunks(MAX_PHRASES).collect();
+        let mut manager = ReportManager::new(self.client).with_poll_interval(self.poll_interval);
+        for chunk in &chunks {
+            let mut request = ReportRequest::new().with_geo(&self.geo_id);
+            for queued in chunk.iter() {
+                request = request.add_phrase(&queued.phrase)?;
+            }
+            manager = manager.add_request(request);
+        }
+        state.reports += chunks.len();
+        state.submitted += batch.len();
+
+        let mut done = vec![false; chunks.len()];
+        let mut first_error = None;
+        while let Some(outcome) = manager.next_outcome().await {
+            let outcome = match outcome {
+                Ok(outcome) => { outcome }
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                    break;
+                }
+            };
+            match outcome.result {
+                Ok(entries) => {
+                    self.add_entries(state, chunks[outcome.index], &entries);
+                    done[outcome.index] = true;
+                }
+                Err(error) => { first_error.get_or_insert(error); }
+            }
+        }
+        // The manager has tried to delete its reports, the ones it failed to are deleted later
+        state.undeleted.extend(manager.take_undeleted());
+
+        let Some(error) = first_error else { return Ok(()) };
+        for (chunk, _) in chunks.iter().zip(done).filter(|(_, done)| !done).rev() {
+            state.submitted -= chunk.len();
+            for queued in chunk.iter().rev() {
+                state.queue.push_front(queued.clone());
+            }
+        }
+        Err(error)
+    }
+
+    fn add_entries(&self, state: &mut CrawlState, chunk: &[Queued], entries: &[ReportEntry]) {
+        state.graph.add_entries(entries);
+        for entry in entries {
+            let Some(queued) = chunk.iter().find(|queued| queued.phrase == entry.phrase) else { continue };
+            let depth = queued.depth + 1;
+            if depth > self.max_depth { continue; }
+            let items = entry.searched_with.iter().chain(entry.searched_also.iter());
+            for item in items.filter(|item| item.shows >= self.min_shows) {
+                // Keywords that are not valid phrases stay in the graph but can't be submitted
+                let Ok(key) = normalize_phrase(&item.phrase) else { continue };
+                if state.seen.insert(key) {
+                    state.queue.push_back(Queued { phrase: item.phrase.clone(), depth });
+                }
+            }
+        }
+    }
+
+    async fn save(&self, state: &CrawlState) -> Result<(), WordstatError> {
+        match &self.state_file {
+            Some(path) => { state.store(path).await }
+            None => { Ok(()) }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Queued {
+    phrase: String,
+    depth: u32
+}
+
+#[derive(Default)]
+struct CrawlState {
+    queue: VecDeque<Queued>,
+    seen: HashSet<String>,
+    submitted: usize,
+    reports: usize,
+    undeleted: Vec<i64>,
+    graph: KeywordGraph
+}
+
+/// The contents of a state file.
+///
+/// The graph has its own models instead of the serde derives of [KeywordNode](crate::keyword_graph::KeywordNode)
+/// and [KeywordEdge]: those exist only with the `serde` feature,
+/// while a state file must be readable whatever features the crate is built with.
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    queue: Vec<QueuedModel>,
+    seen: Vec<String>,
+    submitted: usize,
+    reports: usize,
+    #[serde(default)]
+    undeleted: Vec<i64>,
+    nodes: Vec<NodeModel>,
+    edges: Vec<EdgeModel>
+}
+
+#[derive(Serialize, Deserialize)]
+struct QueuedModel {
+    phrase: String,
+    depth: u32
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeModel {
+    phrase: String,
+    shows: Option<i64>
+}
+
+#[derive(Serialize, Deserialize)]
+struct EdgeModel {
+    source: String,
+    target: String,
+    relation: String,
+    shows: i64
+}
+
+impl CrawlState {
+    /// Reads the state file, returns None if it does not exist
+    async fn load(path: &Path) -> Result<Option<Self>, WordstatError> {
+        let contents = match tokio::fs::read(path).await {
+            Ok(contents) => { contents }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => { return Ok(None); }
+            Err(error) => { return Err(error.into()); }
+        };
+        let file: StateFile = serde_json::from_slice(&contents).map_err(std::io::Error::from)?;
+
+        let mut graph = KeywordGraph::new();
+        for node in file.nodes {
+            graph.add_node(&node.phrase, node.shows);
+        }
+        for edge in file.edges {
+            let Some(relation) = Relation::parse(&edge.relation) else {
+                return Err(invalid_data(format!("unknown relation '{}'", edge.relation)));
+            };
+            // The exports look up both ends of every edge among the nodes
+            for phrase in [&edge.source, &edge.target] {
+                if graph.node(phrase).is_none() {
+                    return Err(invalid_data(format!("edge refers to the missing node '{phrase}'")));
+                }
+            }
+            graph.add_edge(KeywordEdge { source: edge.source, target: edge.target, relation, shows: edge.shows });
+        }
+
+        Ok(Some(CrawlState {
+            queue: file.queue.into_iter().map(|queued| Queued { phrase: queued.phrase, depth: queued.depth }).collect(),
+            seen: file.seen.into_iter().collect(),
+            submitted: file.submitted,
+            reports: file.reports,
+            undeleted: file.undeleted,
+            graph
+        }))
+    }
+
+    async fn store(&self, path: &Path) -> Result<(), WordstatError> {
+        let mut seen: Vec<String> = self.seen.iter().cloned().collect();
+        seen.sort();
+        let file = StateFile {
+            queue: self.queue.iter().map(|queued| QueuedModel { phrase: queued.phrase.clone(), depth: queued.depth }).collect(),
+            seen,
+            submitted: self.submitted,
+            reports: self.reports,
+            undeleted: self.undeleted.clone(),
+            nodes: self.graph.nodes().iter().map(|node| NodeModel { phrase: node.phrase.clone(), shows: node.shows }).collect(),
+            edges: self.graph.edges().iter()
+                .map(|edge| EdgeModel {
+                    source: edge.source.clone(),
+                    target: edge.target.clone(),
+                    relation: edge.relation.as_str().to_string(),
+                    shows: edge.shows
+                })
+                .collect()
+        };
+        let contents = serde_json::to_vec(&file).expect("Crawl state is always serializable");
+
+        // An interrupted crawl never leaves a broken state
+        write_atomically(path, contents).await?;
+        Ok(())
+    }
+}
+
+fn invalid_data(reason: String) -> WordstatError {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, reason).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_report::WordstatItem;
+    use crate::mock_server::MockServer;
+
+    fn entry(phrase: &str, keywords: &[(&str, i64)]) -> ReportEntry {
+        ReportEntry {
+            phrase: phrase.to_string(),
+            geo_id: vec![],
+            searched_with: keywords.iter()
+                .map(|(phrase, shows)| WordstatItem { phrase: phrase.to_string(), shows: *shows })
+                .collect(),
+            searched_also: vec![]
+        }
+    }
+
+    fn server() -> MockServer {
+        MockServer::start()
+            .with_entry(entry("rust", &[("rust", 1000), ("rust lang", 500), ("Rust  Lang", 400), ("rust game", 5)]))
+            .with_entry(entry("rust lang", &[("rust lang", 500), ("rust book", 300), ("rust", 900)]))
+            .with_entry(entry("rust book", &[("rust book", 300), ("rust book pdf", 100)]))
+    }
+
+    fn client(server: &MockServer) -> Client {
+        Client::new(&server.token(), &server.url())
+    }
+
+    fn submitted(server: &MockServer) -> usize {
+        server.calls().iter().filter(|method| *method == "CreateNewWordstatReport").count()
+    }
+
+    #[tokio::test]
+    async fn breadth_first_with_limits() {
+        let server = server();
+        let client = client(&server);
+
+
+        let received = KeywordCrawler::new(&client)
+            .with_seeds(&["rust"])
+            .with_max_depth(3)
+            .with_min_shows(10)
+            .with_poll_interval(Duration::from_millis(1))
+            .run()
+            .await
+            .unwrap();
+
+
+        let phrases: Vec<&str> = received.nodes().iter().map(|node| node.phrase.as_str()).collect();
+        assert_eq!(phrases, ["rust", "rust lang", "Rust  Lang", "rust game", "rust book", "rust book pdf"]);
+        assert_eq!(received.edges().len(), 6);
+        // rust, then rust lang, then rust book, then rust book pdf
+        assert_eq!(submitted(&server), 4)
+    }
+
+    #[tokio::test]
+    async fn resumes_from_state_file() {
+        let server = server();
+        let client = client(&server);
+        let directory = tempfile::tempdir().unwrap();
+        let state_file = directory.path().join("crawl.json");
+        let crawler = |max_reports| KeywordCrawler::new(&client)
+            .with_seeds(&["rust"])
+            .with_max_depth(2)
+            .with_max_reports(max_reports)
+            .with_state_file(&state_file)
+            .with_poll_interval(Duration::from_millis(1));
+
+
+        let stopped = crawler(1).run().await.unwrap();
+        let resumed = crawler(10).run().await.unwrap();
+
+
+        assert_eq!(stopped.nodes().len(), 4);
+        assert_eq!(resumed.nodes().len(), 6);
+        assert_eq!(resumed.node("rust book").unwrap().shows, Some(300));
+        assert_eq!(submitted(&server), 3)
+    }
+
+    #[tokio::test]
+    async fn failed_report_is_retried_on_resume() {
+        let server = server();
+        let client = client(&server);
+        let directory = tempfile::tempdir().unwrap();
+        let state_file = directory.path().join("crawl.json");
+        let crawler = KeywordCrawler::new(&client)
+            .with_seeds(&["rust"])
+            .with_max_depth(0)
+            .with_state_file(&state_file)
+            .with_poll_interval(Duration::from_millis(1));
+        server.inject_error("CreateNewWordstatReport", 152);
+
+
+        let failed = crawler.run().await;
+        let resumed = crawler.run().await.unwrap();
+
+
+        assert!(matches!(failed, Err(WordstatError::QuotaExhausted { .. })));
+        assert_eq!(resumed.nodes().len(), 4)
+    }
+
+    #[tokio::test]
+    async fn deletes_leftover_reports_on_resume() {
+        let server = server();
+        let client = client(&server);
+        let directory = tempfile::tempdir().unwrap();
+        let state_file = directory.path().join("crawl.json");
+        let crawler = KeywordCrawler::new(&client)
+            .with_seeds(&["rust"])
+            .with_max_depth(0)
+            .with_state_file(&state_file)
+            .with_poll_interval(Duration::from_millis(1));
+        server.inject_error("GetWordstatReportList", 500);
+        server.inject_error("DeleteWordstatReport", 500);
+
+
+        let failed = crawler.run().await;
+        let leftover = server.report_ids();
+        let resumed = crawler.run().await.unwrap();
+
+
+        assert!(matches!(failed, Err(WordstatError::InternalServerError { .. })));
+        assert_eq!(leftover.len(), 1);
+        assert_eq!(resumed.nodes().len(), 4);
+        assert!(server.report_ids().is_empty())
+    }
+
+    #[test]
+    fn matches_entries_by_phrase() {
+        let client = Client::new("token", "api_url");
+        let crawler = KeywordCrawler::new(&client).with_max_depth(1);
+        let chunk = [Queued { phrase: "rust".to_string(), depth: 0 }, Queued { phrase: "cargo".to_string(), depth: 1 }];
+        let entries = [entry("cargo", &[("cargo build", 100)]), entry("rust", &[("rust lang", 500)])];
+        let mut state = CrawlState::default();
+
+
+        crawler.add_entries(&mut state, &chunk, &entries);
+
+
+        let queued: Vec<(&str, u32)> = state.queue.iter().map(|queued| (queued.phrase.as_str(), queued.depth)).collect();
+        assert_eq!(queued, vec![("rust lang", 1)])
+    }
+
+    #[tokio::test]
+    async fn rejects_dangling_edges() {
+        let directory = tempfile::tempdir().unwrap();
+        let state_file = directory.path().join("crawl.json");
+        let state = serde_json::json!({
+            "queue": [], "seen": [], "submitted": 1, "reports": 1,
+            "nodes": [{"phrase": "rust", "shows": 1000}],
+            "edges": [{"source": "rust", "target": "rust lang", "relation": "searched_with", "shows": 500}]
+        });
+        std::fs::write(&state_file, state.to_string()).unwrap();
+
+
+        let received = CrawlState::load(&state_file).await;
+
+
+        assert!(matches!(received, Err(WordstatError::Io { source }) if source.kind() == std::io::ErrorKind::InvalidData))
+    }
+}
@@ -1,6 +1,19 @@
+//! Building report requests and starting the report generation.
+//!
+//! Region IDs are not checked by [create_report], which sends them as is. Validation is opt-in:
+//! call [validate_geo](ReportRequest::validate_geo) or, when the [RegionTree] is already built,
+//! [validate_geo_tree](ReportRequest::validate_geo_tree) before sending the request.
 use serde_json::Value;
-use crate::{WordstatError, check_status};
+use crate::WordstatError;
 use crate::client::Client;
+use crate::region::Region;
+use crate::region_tree::RegionTree;
+use crate::phrase::ToPhrase;
+use crate::normalize::normalize_phrase;
+use crate::get_report::ReportEntry;
+
+/// The maximum amount of phrases in a single report
+pub(crate) const MAX_PHRASES: usize = 10;
 
 /// ReportRequest object is used to define the keywords
 /// and regions used to get the statistics about keywords.
@@ -14,26 +27,27 @@ use crate::client::Client;
 /// ```
 ///
 /// Geo is optional
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ReportRequestModel"))]
 pub struct ReportRequest {
     phrases: Vec<String>,
-    geo_id: Vec<i64>
+    geo_id: Vec<i64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    dedupe: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    phrase_mapping: Vec<(String, String)>
 }
 
 impl ReportRequest {
     /// Create a new ReportRequest object
     pub fn new() -> Self {
-        ReportRequest { phrases: vec![], geo_id: vec![] }
+        ReportRequest { phrases: vec![], geo_id: vec![], dedupe: false, phrase_mapping: vec![] }
     }
     /// Add phrases to ReportRequest
     /// Will return an Err if more than 10 phrases were supplied or
-    /// an the phrase contains an character that is not allowed:
-    ///
-    /// Characters that are not allowed:
-    /// - Plus sign '+'
-    /// - Percent sign '%'
-    /// - Ampresand '&'
-    /// - Colon ':'
-    /// - Minus sign with spaces on both sides ' - '
+    /// the phrase is not valid Wordstat query syntax, see [Query](crate::query::Query) for the supported
+    /// operators and the characters that are not allowed.
     ///
     /// To avoid searching a word you can prefix it with '-', like this:
     /// ```
@@ -45,67 +59,370 @@ impl ReportRequest {
     /// ```
     /// # use wordstat_rs::*;
     /// let request = ReportRequest::new()
-    ///     .add_phrase("car -(diesel engine) repair)").unwrap();
+    ///     .add_phrase("car -(diesel engine) repair").unwrap();
     /// ```
-    pub fn add_phrase(mut self, phrase: &str) -> Result<Self, WordstatError> {
+    ///
+    /// Besides strings, a [Phrase](crate::phrase::Phrase) or a [Query](crate::query::Query) can be added.
+    pub fn add_phrase<P: ToPhrase + ?Sized>(mut self, phrase: &P) -> Result<Self, WordstatError> {
         // API does not support more than 10 keyphrases in a single request
-        if self.phrases.len() >= 10 { return Err(WordstatError::TooManyKeyphrases); }
-        self.phrases.push(ReportRequest::check_phrase(phrase)?.to_string());
+        let full = self.phrases.len() >= MAX_PHRASES;
+        if full && !self.dedupe { return Err(WordstatError::TooManyKeyphrases); }
+        let phrase = phrase.to_phrase()?;
+        let submitted = if self.dedupe { normalize_phrase(&phrase)? } else { phrase.clone() };
+        if !self.dedupe || !self.phrases.contains(&submitted) {
+            if full { return Err(WordstatError::TooManyKeyphrases); }
+            self.phrases.push(submitted.clone());
+        }
+        self.phrase_mapping.push((phrase, submitted));
         Ok(self)
     }
     /// Pass a vector of phrases instead of inserting them one by one.
     /// Returns the same errors as [add_phrase](ReportRequest::add_phrase) method.
+    /// Call [with_dedupe](ReportRequest::with_dedupe) first to skip near-duplicates.
     pub fn with_phrases(mut self, phrases: &Vec<&str>) -> Result<Self, WordstatError> {
         for phrase in phrases {
-            self = self.add_phrase(&phrase)?;
+            self = self.add_phrase(phrase)?;
         }
         Ok(self)
     }
+    /// Submits phrases in the [normalized](crate::normalize::normalize_phrase) form and skips
+    /// the ones whose normalized form was already added, so near-duplicates like
+    /// "Купить ёлку" and "ёлку  купить" take a single slot of the 10.
+    /// Phrases added before the call are deduplicated as well.
+    ///
+    /// Use [fan_out](ReportRequest::fan_out) to get the results for every original phrase.
+    /// ```
+    /// # use wordstat_rs::*;
+    /// let request = ReportRequest::new()
+    ///     .with_dedupe()
+    ///     .with_phrases(&vec!["Купить ёлку", "ёлку  купить", "купить елку -дешево"]).unwrap();
+    ///
+    /// assert_eq!(request.phrases(), ["елку купить", "елку купить -дешево"]);
+    /// ```
+    pub fn with_dedupe(mut self) -> Self {
+        self.dedupe = true;
+        self.phrases.clear();
+        for (phrase, submitted) in &mut self.phrase_mapping {
+            *submitted = normalize_phrase(phrase).unwrap_or_else(|_| phrase.clone());
+            if !self.phrases.contains(submitted) { self.phrases.push(submitted.clone()); }
+        }
+        self
+    }
     /// Add region ID to be used when getting statistics.
     /// To get the list of regions use [get_regions](crate::region::get_regions) function.
+    ///
+    /// A negative ID excludes the region, for example `.add_geo(225).add_geo(-213)`
+    /// means Russia except Moscow.
     pub fn add_geo(mut self, geo_id: i64) -> Self {
         self.geo_id.push(geo_id);
         self
     }
+    /// Exclude the region from the statistics. Same as passing the negated ID
+    /// to [add_geo](ReportRequest::add_geo).
+    ///
+    /// Region 0 stands for all regions and can not be excluded, `exclude_geo(0)` does nothing.
+    pub fn exclude_geo(self, geo_id: i64) -> Self {
+        if geo_id == 0 { return self; }
+        self.add_geo(-geo_id.abs())
+    }
     /// Same as [add_geo](ReportRequest::add_geo) but takes a vector of items instead of
     /// a single one.
-    pub fn with_geo(mut self, geo_ids: &Vec<i64>) -> Self {
-        self.geo_id = geo_ids.clone();
+    pub fn with_geo(mut self, geo_ids: &[i64]) -> Self {
+        self.geo_id = geo_ids.to_vec();
         self
     }
-    fn check_phrase(phrase: &str) -> Result<&str, WordstatError> {
-        if phrase.contains("&") {
-            return Err(WordstatError::BadKeyphrase { reason: "Cant use '&' in keyphrases" })
+    /// Returns the phrases added to the request
+    pub fn phrases(&self) -> &[String] {
+        &self.phrases
+    }
+    /// Returns pairs of each phrase passed to [add_phrase](ReportRequest::add_phrase)
+    /// and the phrase submitted for it, in the order they were added
+    pub fn phrase_mapping(&self) -> &[(String, String)] {
+        &self.phrase_mapping
+    }
+    /// Copies the report entries of the submitted phrases to every original phrase
+    /// they were submitted for, in the order the phrases were added.
+    /// Phrases without an entry in the report are skipped.
+    pub fn fan_out(&self, entries: &[ReportEntry]) -> Vec<ReportEntry> {
+        self.phrase_mapping.iter()
+            .filter_map(|(phrase, submitted)| {
+                let entry = entries.iter().find(|entry| entry.phrase == *submitted)?;
+                Some(ReportEntry { phrase: phrase.clone(), ..entry.clone() })
+            })
+            .collect()
+    }
+    /// Returns the region IDs added to the request, negative for excluded regions
+    pub fn geo_id(&self) -> &[i64] {
+        &self.geo_id
+    }
+    /// Checks the region IDs against the list of regions before sending the request.
+    ///
+    /// Returns [UnknownRegion](WordstatError::UnknownRegion) for IDs that are not in the list and
+    /// [RedundantRegion](WordstatError::RedundantRegion) for IDs that are already covered by
+    /// another one, like a city added along with its country or a region listed twice.
+    /// ```rust,ignore
+    /// let regions = get_regions(&client).await?;
+    /// request.validate_geo(&regions)?;
+    /// let report_id = create_report(&client, &request).await?;
+    /// ```
+    /// Builds a [RegionTree] on every call, use [validate_geo_tree](ReportRequest::validate_geo_tree)
+    /// to validate many requests against the same regions.
+    pub fn validate_geo(&self, regions: &[Region]) -> Result<(), WordstatError> {
+        self.validate_geo_tree(&RegionTree::new(regions.to_vec())?)
+    }
+    /// Same as [validate_geo](ReportRequest::validate_geo) with an already built [RegionTree]
+    pub fn validate_geo_tree(&self, tree: &RegionTree) -> Result<(), WordstatError> {
+        for (position, geo_id) in self.geo_id.iter().enumerate() {
+            if tree.get(geo_id.abs()).is_none() {
+                return Err(WordstatError::UnknownRegion { id: *geo_id });
+            }
+            if self.geo_id[..position].contains(geo_id) {
+                return Err(WordstatError::RedundantRegion { id: *geo_id, covered_by: *geo_id });
+            }
+            // Included regions can be covered by included ones, excluded by excluded ones
+            let covering = self.geo_id.iter()
+                .filter(|other| other.signum() == geo_id.signum())
+                .find(|other| tree.is_ancestor(other.abs(), geo_id.abs()));
+            if let Some(covered_by) = covering {
+                return Err(WordstatError::RedundantRegion { id: *geo_id, covered_by: *covered_by });
+            }
         }
-        if phrase.contains("%") {
-            return Err(WordstatError::BadKeyphrase { reason: "Cant use '%' in keyphrases" })
+
+        Ok(())
+    }
+}
+
+impl Default for ReportRequest {
+    fn default() -> Self {
+        ReportRequest::new()
+    }
+}
+
+/// A deserialized request, checked by building it again with [ReportRequest::add_phrase]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+pub(crate) struct ReportRequestModel {
+    phrases: Vec<String>,
+    geo_id: Vec<i64>,
+    #[serde(default)]
+    dedupe: bool,
+    #[serde(default)]
+    phrase_mapping: Vec<(String, String)>
+}
+
+/// A request with the API's field names, see [PascalCase](crate::pascal_case::PascalCase)
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct PascalCaseRequestModel {
+    #[serde(rename = "Phrases")]
+    phrases: Vec<String>,
+    #[serde(rename = "GeoID")]
+    geo_id: Vec<i64>,
+    #[serde(rename = "Dedupe", default)]
+    dedupe: bool,
+    #[serde(rename = "PhraseMapping", default)]
+    phrase_mapping: Vec<(String, String)>
+}
+
+#[cfg(feature = "serde")]
+impl From<&ReportRequest> for PascalCaseRequestModel {
+    fn from(request: &ReportRequest) -> Self {
+        PascalCaseRequestModel {
+            phrases: request.phrases.clone(),
+            geo_id: request.geo_id.clone(),
+            dedupe: request.dedupe,
+            phrase_mapping: request.phrase_mapping.clone()
         }
-        if phrase.contains("+") {
-            return Err(WordstatError::BadKeyphrase { reason: "Cant use '+' in keyphrases" })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<PascalCaseRequestModel> for ReportRequestModel {
+    fn from(model: PascalCaseRequestModel) -> Self {
+        ReportRequestModel {
+            phrases: model.phrases,
+            geo_id: model.geo_id,
+            dedupe: model.dedupe,
+            phrase_mapping: model.phrase_mapping
         }
-        if phrase.contains(" - ") {
-            return Err(WordstatError::BadKeyphrase { reason: "Cant use ' - ' in keyphrases" })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ReportRequestModel> for ReportRequest {
+    type Error = WordstatError;
+
+    fn try_from(model: ReportRequestModel) -> Result<Self, Self::Error> {
+        let mut request = ReportRequest::new().with_geo(&model.geo_id);
+        if model.dedupe { request = request.with_dedupe(); }
+        // Requests serialized before the mapping was added only have the phrases
+        let originals: Vec<&String> = match model.phrase_mapping.is_empty() {
+            true => { model.phrases.iter().collect() }
+            false => { model.phrase_mapping.iter().map(|(original, _)| original).collect() }
+        };
+        for phrase in originals {
+            request = request.add_phrase(phrase)?;
         }
-        if phrase.contains(":") {
-            return Err(WordstatError::BadKeyphrase { reason: "Cant use ':' in keyphrases" })
+
+        if request.phrases != model.phrases || (!model.phrase_mapping.is_empty() && request.phrase_mapping != model.phrase_mapping) {
+            return Err(WordstatError::MismatchedPhraseMapping);
         }
-        Ok(phrase)
+        Ok(request)
     }
 }
 
+pub(crate) const METHOD: &str = "CreateNewWordstatReport";
+
 /// Sends the request to the API using Wordstat client to start the report generation.
+/// The region IDs are not validated, see [validate_geo](ReportRequest::validate_geo).
 pub async fn create_report(client: &Client, request: &ReportRequest) -> Result<i64, WordstatError> {
-    let method = "CreateNewWordstatReport";
+    client.call(METHOD, Some(request_params(request))).await
+}
+
+pub(crate) fn request_params(request: &ReportRequest) -> Value {
     let mut params = serde_json::Map::new();
     params.insert("Phrases".to_string(), Value::from(request.phrases.clone()));
     params.insert("GeoID".to_string(), Value::from(request.geo_id.clone()));
-    let result = client.post(method, Some(params.into())).await?;
+    params.into()
+}
 
-    check_status(&result)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions() -> Vec<Region> {
+        vec![
+            Region { name: "All".to_string(), id: 0, parent_id: None },
+            Region { name: "Russia".to_string(), id: 225, parent_id: Some(0) },
+            Region { name: "Moscow and Moscow Oblast".to_string(), id: 1, parent_id: Some(225) },
+            Region { name: "Moscow".to_string(), id: 213, parent_id: Some(1) },
+            Region { name: "Saint Petersburg".to_string(), id: 2, parent_id: Some(225) },
+        ]
+    }
+
+    #[test]
+    fn valid_geo() {
+        let request = ReportRequest::new()
+            .add_geo(225)
+            .exclude_geo(1)
+            .add_geo(-2);
+
+
+        assert!(request.validate_geo(&regions()).is_ok())
+    }
+
+    #[test]
+    fn unknown_geo() {
+        let request = ReportRequest::new().add_geo(225).add_geo(-54);
+
+
+        let received = request.validate_geo(&regions());
+
+
+        assert!(matches!(received, Err(WordstatError::UnknownRegion { id: -54 })))
+    }
+
+    #[test]
+    fn redundant_geo() {
+        let city_and_country = ReportRequest::new().add_geo(213).add_geo(225);
+        let repeated = ReportRequest::new().add_geo(2).add_geo(2);
+        let excluded_twice = ReportRequest::new().add_geo(225).add_geo(-1).add_geo(-213);
+
+
+        assert!(matches!(city_and_country.validate_geo(&regions()), Err(WordstatError::RedundantRegion { id: 213, covered_by: 225 })));
+        assert!(matches!(repeated.validate_geo(&regions()), Err(WordstatError::RedundantRegion { id: 2, covered_by: 2 })));
+        assert!(matches!(excluded_twice.validate_geo(&regions()), Err(WordstatError::RedundantRegion { id: -213, covered_by: -1 })))
+    }
+
+    #[test]
+    fn exclude_all_regions() {
+        let request = ReportRequest::new()
+            .add_geo(225)
+            .exclude_geo(0);
+
+
+        let received = request.geo_id();
+
+
+        assert_eq!(received, &[225])
+    }
+
+    #[test]
+    fn shared_region_tree() {
+        let tree = RegionTree::new(regions()).unwrap();
+        let valid = ReportRequest::new().add_geo(225).exclude_geo(213);
+        let unknown = ReportRequest::new().add_geo(54);
+
+
+        assert!(valid.validate_geo_tree(&tree).is_ok());
+        assert!(matches!(unknown.validate_geo_tree(&tree), Err(WordstatError::UnknownRegion { id: 54 })))
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        let request = ReportRequest::new()
+            .add_phrase("rust lang").unwrap()
+            .add_geo(213);
+
+
+        let serialized = serde_json::to_string(&request).unwrap();
+        let received: ReportRequest = serde_json::from_str(&serialized).unwrap();
+
+
+        assert_eq!(received.phrases, request.phrases);
+        assert_eq!(received.geo_id, request.geo_id)
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn deserialize_validates() {
+        let request = ReportRequest::new()
+            .with_dedupe()
+            .with_phrases(&vec!["Rust  lang", "lang rust"]).unwrap();
+        let mut mismatched = serde_json::to_value(&request).unwrap();
+        mismatched["phrases"][0] = "cargo".into();
+        let too_many = serde_json::json!({"phrases": vec!["rust"; 11], "geo_id": []});
+        let bad_syntax = serde_json::json!({"phrases": ["car -(diesel engine) repair)"], "geo_id": []});
+
+
+        let received: ReportRequest = serde_json::from_value(serde_json::to_value(&request).unwrap()).unwrap();
+
+
+        assert_eq!(received.phrase_mapping, request.phrase_mapping);
+        let mismatched_error = serde_json::from_value::<ReportRequest>(mismatched).unwrap_err();
+        assert_eq!(mismatched_error.to_string(), WordstatError::MismatchedPhraseMapping.to_string());
+        assert!(serde_json::from_value::<ReportRequest>(too_many).is_err());
+        assert!(serde_json::from_value::<ReportRequest>(bad_syntax).is_err())
+    }
+
+    #[test]
+    fn invalid_phrase_syntax() {
+        let request = ReportRequest::new();
+
+
+        let received = request.add_phrase("car -(diesel engine) repair)");
+
+
+        assert!(matches!(received, Err(WordstatError::BadKeyphrase { position: 27, .. })))
+    }
+
+    #[test]
+    fn dedupe_phrases() {
+        let request = ReportRequest::new()
+            .add_phrase("Rust  lang").unwrap()
+            .with_dedupe()
+            .with_phrases(&vec!["lang rust", "rust book", "RUST LANG -steel -steel"]).unwrap();
+        let entries: Vec<ReportEntry> = ["lang rust", "book rust"].iter()
+            .map(|phrase| ReportEntry { phrase: phrase.to_string(), geo_id: vec![], searched_with: vec![], searched_also: vec![] })
+            .collect();
 
-    let Some(data) = result.get("data") else { return Err(WordstatError::BadResponse{ reason: "Data field not found in response" }) };
-    let Value::Number(report_id) = data else { return Err(WordstatError::BadResponse{ reason: "Data field is not a number" }) };
-    if !report_id.is_i64() { return Err(WordstatError::BadResponse{ reason: "Data field is not an integer" }) }
 
-    Ok(report_id.as_i64().unwrap() as i64)
+        let received = request.fan_out(&entries);
+
+
+        assert_eq!(request.phrases(), ["lang rust", "book rust", "lang rust -steel"]);
+        let phrases: Vec<&str> = received.iter().map(|entry| entry.phrase.as_str()).collect();
+        assert_eq!(phrases, ["Rust  lang", "lang rust", "rust book"])
+    }
 }
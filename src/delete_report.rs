@@ -1,18 +1,19 @@
 use serde_json::Value;
-use crate::{WordstatError, check_status};
+use crate::WordstatError;
 use crate::client::Client;
 
+pub(crate) const METHOD: &str = "DeleteWordstatReport";
+
 /// Sends the request to the API using Wordstat client to delete the report with
 /// the passed report_id.
 pub async fn delete_report(client: &Client, report_id: i64) -> Result<(), WordstatError> {
-    let method = "DeleteWordstatReport";
     let params = Value::Number(report_id.into());
-    let result = client.post(method, Some(params)).await?;
+    let return_code: i64 = client.call(METHOD, Some(params)).await?;
 
-    check_status(&result)?;
-    let Some(data_val) = result.get("data") else { return Err(WordstatError::BadResponse{ reason: "Data field not found in response" }) };
-    let Some(return_code) = data_val.as_i64() else { return Err(WordstatError::BadResponse{ reason: "Data field is not an integer" }) };
+    check_delete_result(return_code)
+}
 
+pub(crate) fn check_delete_result(return_code: i64) -> Result<(), WordstatError> {
     if return_code != 1 {
         Err(WordstatError::UnknownError)
     }
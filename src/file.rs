@@ -0,0 +1,44 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Writes the contents to a temporary file next to `path` and renames it over `path`,
+/// so that readers never see a partially written file.
+///
+/// The temporary name is unique within the process, so files sharing a directory
+/// or written concurrently do not overwrite each other's temporary files.
+pub(crate) async fn write_atomically(path: &Path, contents: Vec<u8>) -> std::io::Result<()> {
+    let temporary = temporary_path(path);
+    let result = match tokio::fs::write(&temporary, contents).await {
+        Ok(()) => { tokio::fs::rename(&temporary, path).await }
+        Err(error) => { Err(error) }
+    };
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temporary).await;
+    }
+    result
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let unique = COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{name}.{}.{unique}.tmp", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_temporary_paths() {
+        let path = Path::new("cache/regions.json");
+
+
+        let received = [temporary_path(path), temporary_path(path)];
+
+
+        assert_ne!(received[0], received[1]);
+        assert_eq!(received[0].parent(), path.parent())
+    }
+}
@@ -1,12 +1,12 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use crate::{WordstatError, check_status};
-use mockall_double::double;
-#[double] // For mocking the client in unit tests
+use crate::WordstatError;
 use crate::client::Client;
 
 /// Describes a single keyword
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WordstatItem {
     /// The exact phrase searched
     pub phrase: String,
@@ -15,8 +15,9 @@ pub struct WordstatItem {
 }
 
 /// Describes a report about a single keyword
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReportEntry {
     /// The phrase, used to generate the ReportEntry
     pub phrase: String,
@@ -25,101 +26,90 @@ pub struct ReportEntry {
     /// The phrases containing the passed phrase
     pub searched_with: Vec<WordstatItem>,
     /// Similar phrases
+    #[cfg_attr(feature = "serde", serde(default))]
     pub searched_also: Vec<WordstatItem>
 }
 
+pub(crate) const METHOD: &str = "GetWordstatReport";
+
 /// Send a request to the API asking for a report with the passed ID
 pub async fn get_report(client: &Client, report_id: i64) -> Result<Vec<ReportEntry>, WordstatError> {
-    let method = "GetWordstatReport";
     let params = Value::Number(report_id.into());
-    let result = client.post(method, Some(params)).await?;
-
-    check_status(&result)?;
-
-    let Some(data_val) = result.get("data") else { return Err(WordstatError::BadResponse{ reason: "No data field in response" }) };
-    let Value::Array(data) = data_val else { return Err(WordstatError::BadResponse{ reason: "Data field is not an array" }) };
+    let report: Vec<ReportEntryModel> = client.call(METHOD, Some(params)).await?;
 
-    parse_report(&data)
+    Ok(report.into_iter().map(ReportEntry::from).collect())
 }
 
-fn parse_report(data: &Vec<Value>) -> Result<Vec<ReportEntry>, WordstatError> {
-    let mut report: Vec<ReportEntry> = vec![];
-
-    for item in data {
-        report.push(parse_report_entry(item)?);
-    }
-
-    Ok(report)
+/// A report entry as it is returned by the API
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ReportEntryModel {
+    #[serde(rename = "Phrase")]
+    phrase: String,
+    #[serde(rename = "GeoID")]
+    geo_id: Vec<i64>,
+    #[serde(rename = "SearchedWith")]
+    searched_with: Vec<WordstatItemModel>,
+    // This field is optional and can be absent with less popular keywords
+    #[serde(rename = "SearchedAlso", default)]
+    searched_also: Vec<WordstatItemModel>
 }
 
-fn parse_report_entry(data: &Value) -> Result<ReportEntry, WordstatError> {
-    let Some(phrase_val) = data.get("Phrase") else { return Err(WordstatError::BadResponse{ reason: "No Phrase field" }) };
-    let Value::String(phrase) = phrase_val else { return Err(WordstatError::BadResponse{ reason: "Phrase field is not a string" }) };
-
-    let Some(geoid_val) = data.get("GeoID") else { return Err(WordstatError::BadResponse{ reason: "No GeoID field" }) };
-    let Value::Array(geoid_arr) = geoid_val else { return Err(WordstatError::BadResponse{ reason: "GeoID field is not an array" }) };
-    let geo_id = parse_geoid(geoid_arr)?;
-
-    let Some(searched_with_val) = data.get("SearchedWith") else { return Err(WordstatError::BadResponse{ reason: "No SearchedWith field" }) };
-    let Value::Array(searched_with_arr) = searched_with_val else { return Err(WordstatError::BadResponse{ reason: "SearchedWith field is not an array" }) };
-    let searched_with = parse_wordstat_items(searched_with_arr)?;
+/// A keyword as it is returned by the API
+#[derive(Serialize, Deserialize)]
+pub(crate) struct WordstatItemModel {
+    #[serde(rename = "Phrase")]
+    phrase: String,
+    #[serde(rename = "Shows")]
+    shows: i64
+}
 
-    // This field is optional and can be absent with less popular keywords
-    let searched_also: Vec<WordstatItem>;
-    if let Some(searched_also_val) = data.get("SearchedAlso") {
-        let Value::Array(searched_also_arr) = searched_also_val else { return Err(WordstatError::BadResponse{ reason: "SearchedAlso field is not an array" }) };
-        searched_also = parse_wordstat_items(searched_also_arr)?;
-    }
-    else {
-        searched_also = vec![];
+impl From<ReportEntryModel> for ReportEntry {
+    fn from(model: ReportEntryModel) -> Self {
+        ReportEntry {
+            phrase: model.phrase,
+            geo_id: model.geo_id,
+            searched_with: model.searched_with.into_iter().map(WordstatItem::from).collect(),
+            searched_also: model.searched_also.into_iter().map(WordstatItem::from).collect()
+        }
     }
-
-    Ok(ReportEntry {
-        phrase: phrase.to_string(),
-        geo_id,
-        searched_with,
-        searched_also
-    })
 }
 
-fn parse_geoid(data: &Vec<Value>) -> Result<Vec<i64>, WordstatError> {
-    let mut geoids: Vec<i64> = vec![];
-
-    for item in data {
-        let Value::Number(geoid) = item else { return Err(WordstatError::BadResponse{ reason: "GeoID is not a number" }) };
-        if !geoid.is_i64() { return Err(WordstatError::BadResponse{ reason: "GeoID is not an integer" }) }
-        geoids.push(geoid.as_i64().unwrap())
+impl From<&ReportEntry> for ReportEntryModel {
+    fn from(entry: &ReportEntry) -> Self {
+        ReportEntryModel {
+            phrase: entry.phrase.clone(),
+            geo_id: entry.geo_id.clone(),
+            searched_with: entry.searched_with.iter().map(WordstatItemModel::from).collect(),
+            searched_also: entry.searched_also.iter().map(WordstatItemModel::from).collect()
+        }
     }
-
-    Ok(geoids)
 }
 
-fn parse_wordstat_items(data: &Vec<Value>) -> Result<Vec<WordstatItem>, WordstatError> {
-    let mut items: Vec<WordstatItem> = vec![];
-
-    for item in data {
-        items.push(parse_wordstat_item(item)?);
+impl From<&WordstatItem> for WordstatItemModel {
+    fn from(item: &WordstatItem) -> Self {
+        WordstatItemModel {
+            phrase: item.phrase.clone(),
+            shows: item.shows
+        }
     }
-
-    Ok(items)
 }
 
-fn parse_wordstat_item(data: &Value) -> Result<WordstatItem, WordstatError> {
-    let Some(phrase_val) = data.get("Phrase") else { return Err(WordstatError::BadResponse{ reason: "Phrase not found in WordstatItem" }) };
-    let Value::String(phrase) = phrase_val else { return Err(WordstatError::BadResponse{ reason: "Phrase in WordstatItem is not a string" }) };
-
-    let Some(shows_val) = data.get("Shows") else { return Err(WordstatError::BadResponse{ reason: "Shows field not found" }) };
-    let Some(shows) = shows_val.as_i64() else { return Err(WordstatError::BadResponse{ reason: "Shows field is not an integer" }) };
-
-    Ok(WordstatItem {
-        phrase: phrase.clone(),
-        shows
-    })
+impl From<WordstatItemModel> for WordstatItem {
+    fn from(model: WordstatItemModel) -> Self {
+        WordstatItem {
+            phrase: model.phrase,
+            shows: model.shows
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::response::deserialize;
+    use crate::transport::MockTransport;
+    #[cfg(feature = "serde")]
+    use crate::pascal_case::PascalCase;
 
     #[test]
     fn parse_wordstat_item() {
@@ -129,7 +119,7 @@ mod tests {
         let input: Value = serde_json::from_str(data).unwrap();
 
 
-        let received = super::parse_wordstat_item(&input).unwrap();
+        let received = WordstatItem::from(deserialize::<WordstatItemModel, _>(&input).unwrap());
 
 
         let expected = WordstatItem {
@@ -148,7 +138,7 @@ mod tests {
         let input: Value = serde_json::from_str(data).unwrap();
 
 
-        let received = super::parse_wordstat_item(&input).unwrap();
+        let received = WordstatItem::from(deserialize::<WordstatItemModel, _>(&input).unwrap());
 
 
         let expected = WordstatItem {
@@ -170,7 +160,7 @@ mod tests {
         let input: Value = serde_json::from_str(data).unwrap();
 
 
-        let received = super::parse_report_entry(&input).unwrap();
+        let received = ReportEntry::from(deserialize::<ReportEntryModel, _>(&input).unwrap());
 
 
         let expected = ReportEntry {
@@ -220,13 +210,14 @@ mod tests {
             "#;
         let return_value = serde_json::from_str(data).unwrap();
 
-        let mut mock_client = Client::default();
-        mock_client.expect_post()
-            .withf(|method, _params| method == "GetWordstatReport")
-            .return_once(move |_method, _params| Ok(return_value));
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .withf(|_url, payload| payload["method"] == "GetWordstatReport")
+            .return_once(move |_url, _payload| Ok(return_value));
+        let client = Client::with_transport("token", "api_url", transport);
 
 
-        let received = futures::executor::block_on(super::get_report(&mock_client, 54)).unwrap();
+        let received = futures::executor::block_on(super::get_report(&client, 54)).unwrap();
 
 
         let expected = vec![
@@ -282,4 +273,58 @@ mod tests {
 
         assert_eq!(received, expected)
     }
+
+    #[cfg(feature = "serde")]
+    fn entry() -> ReportEntry {
+        ReportEntry {
+            phrase: "rust-lang".to_string(),
+            geo_id: vec![213],
+            searched_with: vec![WordstatItem { phrase: "rust-lang how".to_string(), shows: 23 }],
+            searched_also: vec![WordstatItem { phrase: "cpp".to_string(), shows: 432 }]
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        let report = vec![entry()];
+
+
+        let serialized = serde_json::to_string(&report).unwrap();
+        let received: Vec<ReportEntry> = serde_json::from_str(&serialized).unwrap();
+
+
+        assert_eq!(received, report)
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_field_names() {
+        let received = serde_json::to_value(entry()).unwrap();
+
+
+        let expected = serde_json::json!({
+            "phrase": "rust-lang",
+            "geo_id": [213],
+            "searched_with": [{"phrase": "rust-lang how", "shows": 23}],
+            "searched_also": [{"phrase": "cpp", "shows": 432}]
+        });
+        assert_eq!(received, expected)
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_api_field_names() {
+        let data = r#"
+                {"Phrase": "rust-lang", "GeoID": [213],
+                "SearchedWith": [{"Phrase": "rust-lang how", "Shows": 23}],
+                "SearchedAlso": [{"Phrase": "cpp", "Shows": 432}]}
+            "#;
+
+
+        let PascalCase(received): PascalCase<ReportEntry> = serde_json::from_str(data).unwrap();
+
+
+        assert_eq!(received, entry())
+    }
 }
@@ -0,0 +1,426 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use serde_json::json;
+use crate::WordstatError;
+use crate::get_report::ReportEntry;
+
+/// How a keyword is related to the phrase of a [ReportEntry]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Relation {
+    /// The keyword is listed in [searched_with](ReportEntry::searched_with)
+    SearchedWith,
+    /// The keyword is listed in [searched_also](ReportEntry::searched_also)
+    SearchedAlso
+}
+
+impl Relation {
+    /// Returns the name of the relation, `searched_with` or `searched_also`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Relation::SearchedWith => { "searched_with" }
+            Relation::SearchedAlso => { "searched_also" }
+        }
+    }
+
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "searched_with" => { Some(Relation::SearchedWith) }
+            "searched_also" => { Some(Relation::SearchedAlso) }
+            _               => { None }
+        }
+    }
+}
+
+/// A phrase in a [KeywordGraph]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeywordNode {
+    /// The phrase
+    pub phrase: String,
+    /// The largest amount of shows reported for the phrase, if it was listed as a keyword
+    pub shows: Option<i64>
+}
+
+/// A keyword found in the report entry of another phrase
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeywordEdge {
+    /// The phrase of the report entry
+    pub source: String,
+    /// The keyword listed in the entry
+    pub target: String,
+    /// The list of the entry the keyword was found in
+    pub relation: Relation,
+    /// The amount of shows of the keyword
+    pub shows: i64
+}
+
+/// Limits the [KeywordGraph] to the most searched keywords, see [filtered](KeywordGraph::filtered)
+#[derive(Debug, Clone, Default)]
+pub struct GraphFilter {
+    min_shows: i64,
+    top_neighbours: Option<usize>
+}
+
+impl GraphFilter {
+    /// Creates a filter keeping the whole graph
+    pub fn new() -> Self {
+        GraphFilter::default()
+    }
+
+    /// Removes the phrases with fewer shows along with their edges.
+    /// Phrases never listed as a keyword have no shows and are kept.
+    pub fn with_min_shows(mut self, min_shows: i64) -> Self {
+        self.min_shows = min_shows;
+        self
+    }
+
+    /// Keeps only the given amount of keywords with the most shows for every phrase
+    pub fn with_top_neighbours(mut self, top_neighbours: usize) -> Self {
+        self.top_neighbours = Some(top_neighbours);
+        self
+    }
+}
+
+/// Phrases connected with the keywords found in their report entries.
+///
+/// The graph can be exported to [GraphML](KeywordGraph::write_graphml),
+/// [Graphviz DOT](KeywordGraph::write_dot) and a [JSON node-link](KeywordGraph::write_json)
+/// format understood by D3 and NetworkX.
+/// ```
+/// # use wordstat_rs::*;
+/// let entry = ReportEntry {
+///     phrase: "rust".to_string(),
+///     geo_id: vec![],
+///     searched_with: vec![WordstatItem { phrase: "rust lang".to_string(), shows: 50 }],
+///     searched_also: vec![WordstatItem { phrase: "golang".to_string(), shows: 80 }]
+/// };
+/// let graph = KeywordGraph::from_entries(&[entry]);
+///
+/// assert_eq!(graph.nodes().len(), 3);
+/// assert_eq!(graph.edges()[1].relation, Relation::SearchedAlso);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeywordGraph {
+    nodes: Vec<KeywordNode>,
+    edges: Vec<KeywordEdge>,
+    node_index: HashMap<String, usize>,
+    edge_index: HashSet<(String, String, Relation)>
+}
+
+impl KeywordGraph {
+    /// Creates an empty graph
+    pub fn new() -> Self {
+        KeywordGraph::default()
+    }
+
+    /// Creates a graph from report entries
+    pub fn from_entries(entries: &[ReportEntry]) -> Self {
+        let mut graph = KeywordGraph::new();
+        graph.add_entries(entries);
+        graph
+    }
+
+    /// Creates a graph from the entries of several reports
+    pub fn from_reports(reports: &[Vec<ReportEntry>]) -> Self {
+        let mut graph = KeywordGraph::new();
+        for entries in reports {
+            graph.add_entries(entries);
+        }
+        graph
+    }
+
+    /// Adds the phrase of the entry and its keywords, connected with an edge per keyword.
+    /// The entry phrase listed among its own keywords only sets the shows of its node.
+    pub fn add_entry(&mut self, entry: &ReportEntry) {
+        self.add_node(&entry.phrase, None);
+        let items = entry.searched_with.iter().map(|item| (Relation::SearchedWith, item))
+            .chain(entry.searched_also.iter().map(|item| (Relation::SearchedAlso, item)));
+        for (relation, item) in items {
+            self.add_node(&item.phrase, Some(item.shows));
+            if item.phrase == entry.phrase { continue; }
+            self.add_edge(KeywordEdge {
+                source: entry.phrase.clone(),
+                target: item.phrase.clone(),
+                relation,
+                shows: item.shows
+            });
+        }
+    }
+
+    /// Same as [add_entry](KeywordGraph::add_entry) for several entries
+    pub fn add_entries(&mut self, entries: &[ReportEntry]) {
+        for entry in entries {
+            self.add_entry(entry);
+        }
+    }
+
+    /// Returns the phrases in the order they were added
+    pub fn nodes(&self) -> &[KeywordNode] {
+        &self.nodes
+    }
+
+    /// Returns the edges in the order they were added
+    pub fn edges(&self) -> &[KeywordEdge] {
+        &self.edges
+    }
+
+    /// Returns the node of the phrase
+    pub fn node(&self, phrase: &str) -> Option<&KeywordNode> {
+        self.node_index.get(phrase).map(|index| &self.nodes[*index])
+    }
+
+    /// Returns a copy of the graph limited by the filter. Phrases left without edges
+    /// are removed unless they had none in the first place.
+    /// ```
+    /// # use wordstat_rs::*;
+    /// # let graph = KeywordGraph::new();
+    /// let graph = graph.filtered(&GraphFilter::new().with_min_shows(100).with_top_neighbours(5));
+    /// ```
+    pub fn filtered(&self, filter: &GraphFilter) -> KeywordGraph {
+        let kept_node = |phrase: &str| self.node(phrase)
+            .is_some_and(|node| node.shows.is_none_or(|shows| shows >= filter.min_shows));
+        let mut edges: Vec<&KeywordEdge> = self.edges.iter()
+            .filter(|edge| kept_node(&edge.source) && kept_node(&edge.target))
+            .collect();
+        if let Some(top_neighbours) = filter.top_neighbours {
+            // Rank the edges of every phrase by shows, keeping the original order for equal shows
+            let mut ranked: Vec<usize> = (0..edges.len()).collect();
+            ranked.sort_by_key(|index| std::cmp::Reverse(edges[*index].shows));
+            let mut neighbours: HashMap<&str, usize> = HashMap::new();
+            let kept: HashSet<usize> = ranked.into_iter()
+                .filter(|index| {
+                    let count = neighbours.entry(edges[*index].source.as_str()).or_default();
+                    *count += 1;
+                    *count <= top_neighbours
+                })
+                .collect();
+            edges = edges.into_iter().enumerate()
+                .filter(|(index, _)| kept.contains(index))
+                .map(|(_, edge)| edge)
+                .collect();
+        }
+
+        let connected: HashSet<&str> = self.edges.iter()
+            .flat_map(|edge| [edge.source.as_str(), edge.target.as_str()])
+            .collect();
+        let still_connected: HashSet<&str> = edges.iter()
+            .flat_map(|edge| [edge.source.as_str(), edge.target.as_str()])
+            .collect();
+        let mut graph = KeywordGraph::new();
+        for node in &self.nodes {
+            if !kept_node(&node.phrase) { continue; }
+            if connected.contains(node.phrase.as_str()) && !still_connected.contains(node.phrase.as_str()) { continue; }
+            graph.add_node(&node.phrase, node.shows);
+        }
+        for edge in edges {
+            graph.add_edge(edge.clone());
+        }
+        graph
+    }
+
+    /// Writes the graph in the GraphML format. Nodes have the `phrase` and `shows`
+    /// attributes, edges the `relation` and `shows` attributes.
+    pub fn write_graphml<W: Write>(&self, mut writer: W) -> Result<(), WordstatError> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(writer, r#"  <key id="phrase" for="node" attr.name="phrase" attr.type="string"/>"#)?;
+        writeln!(writer, r#"  <key id="shows" for="node" attr.name="shows" attr.type="long"/>"#)?;
+        writeln!(writer, r#"  <key id="relation" for="edge" attr.name="relation" attr.type="string"/>"#)?;
+        writeln!(writer, r#"  <key id="edge_shows" for="edge" attr.name="shows" attr.type="long"/>"#)?;
+        writeln!(writer, r#"  <graph id="keywords" edgedefault="directed">"#)?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            write!(writer, r#"    <node id="n{index}"><data key="phrase">{}</data>"#, escape_xml(&node.phrase))?;
+            if let Some(shows) = node.shows { write!(writer, r#"<data key="shows">{shows}</data>"#)?; }
+            writeln!(writer, "</node>")?;
+        }
+        for edge in &self.edges {
+            writeln!(writer, r#"    <edge source="n{}" target="n{}"><data key="relation">{}</data><data key="edge_shows">{}</data></edge>"#,
+                self.node_index[&edge.source], self.node_index[&edge.target], edge.relation.as_str(), edge.shows)?;
+        }
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        Ok(())
+    }
+
+    /// Writes the graph in the Graphviz DOT format. Keywords from
+    /// [searched_also](ReportEntry::searched_also) are connected with dashed edges.
+    pub fn write_dot<W: Write>(&self, mut writer: W) -> Result<(), WordstatError> {
+        writeln!(writer, "digraph keywords {{")?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            match node.shows {
+                Some(shows) => { writeln!(writer, "  n{index} [label=\"{}\", shows={shows}];", escape_dot(&node.phrase))?; }
+                None => { writeln!(writer, "  n{index} [label=\"{}\"];", escape_dot(&node.phrase))?; }
+            }
+        }
+        for edge in &self.edges {
+            let style = match edge.relation {
+                Relation::SearchedWith => { "solid" }
+                Relation::SearchedAlso => { "dashed" }
+            };
+            writeln!(writer, "  n{} -> n{} [relation=\"{}\", shows={}, style={style}];",
+                self.node_index[&edge.source], self.node_index[&edge.target], edge.relation.as_str(), edge.shows)?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    /// Writes the graph as JSON in the node-link format: the phrases are the `id`s of
+    /// the `nodes` and the `source` and `target` of the `links`.
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), WordstatError> {
+        let nodes: Vec<_> = self.nodes.iter()
+            .map(|node| json!({"id": node.phrase, "shows": node.shows}))
+            .collect();
+        let links: Vec<_> = self.edges.iter()
+            .map(|edge| json!({"source": edge.source, "target": edge.target, "relation": edge.relation.as_str(), "shows": edge.shows}))
+            .collect();
+        let graph = json!({"directed": true, "multigraph": true, "graph": {}, "nodes": nodes, "links": links});
+        serde_json::to_writer(writer, &graph).map_err(std::io::Error::from)?;
+        Ok(())
+    }
+
+    /// Adds the phrase if it is new and raises its shows to the passed amount
+    pub(crate) fn add_node(&mut self, phrase: &str, shows: Option<i64>) {
+        let index = match self.node_index.get(phrase) {
+            Some(index) => { *index }
+            None => {
+                self.nodes.push(KeywordNode { phrase: phrase.to_string(), shows: None });
+                self.node_index.insert(phrase.to_string(), self.nodes.len() - 1);
+                self.nodes.len() - 1
+            }
+        };
+        let node = &mut self.nodes[index];
+        node.shows = node.shows.max(shows);
+    }
+
+    /// Adds the edge unless the same keyword is already connected with the same relation.
+    /// Both phrases must already be in the graph.
+    pub(crate) fn add_edge(&mut self, edge: KeywordEdge) {
+        if self.edge_index.insert((edge.source.clone(), edge.target.clone(), edge.relation)) {
+            self.edges.push(edge);
+        }
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_report::WordstatItem;
+
+    fn entry(phrase: &str, searched_with: &[(&str, i64)], searched_also: &[(&str, i64)]) -> ReportEntry {
+        let items = |items: &[(&str, i64)]| items.iter()
+            .map(|(phrase, shows)| WordstatItem { phrase: phrase.to_string(), shows: *shows })
+            .collect();
+        ReportEntry { phrase: phrase.to_string(), geo_id: vec![], searched_with: items(searched_with), searched_also: items(searched_also) }
+    }
+
+    #[test]
+    fn merge_entries() {
+        let entries = [
+            entry("rust", &[("rust", 100), ("rust lang", 50)], &[("golang", 80)]),
+            entry("rust lang", &[("rust lang", 60), ("rust", 90)], &[]),
+            entry("rust", &[("rust lang", 50)], &[]),
+        ];
+
+
+        let received = KeywordGraph::from_entries(&entries);
+
+
+        let shows: Vec<(&str, Option<i64>)> = received.nodes().iter().map(|node| (node.phrase.as_str(), node.shows)).collect();
+        assert_eq!(shows, [("rust", Some(100)), ("rust lang", Some(60)), ("golang", Some(80))]);
+        let edges: Vec<(&str, &str, Relation)> = received.edges().iter()
+            .map(|edge| (edge.source.as_str(), edge.target.as_str(), edge.relation))
+            .collect();
+        assert_eq!(edges, [
+            ("rust", "rust lang", Relation::SearchedWith),
+            ("rust", "golang", Relation::SearchedAlso),
+            ("rust lang", "rust", Relation::SearchedWith),
+        ])
+    }
+
+    fn graph() -> KeywordGraph {
+        KeywordGraph::from_reports(&[
+            vec![entry("rust", &[("rust", 100), ("rust \"lang\"", 50), ("rust game", 5)], &[("golang", 80)])],
+            vec![entry("golang", &[("golang", 80), ("go & rust", 20)], &[])],
+        ])
+    }
+
+    #[test]
+    fn filter_graph() {
+        let graph = graph();
+
+
+        let by_shows = graph.filtered(&GraphFilter::new().with_min_shows(10));
+        let top = graph.filtered(&GraphFilter::new().with_top_neighbours(1));
+
+
+        let phrases = |graph: &KeywordGraph| graph.nodes().iter().map(|node| node.phrase.clone()).collect::<Vec<_>>();
+        assert_eq!(phrases(&by_shows), ["rust", "rust \"lang\"", "golang", "go & rust"]);
+        assert_eq!(by_shows.edges().len(), 3);
+        assert_eq!(phrases(&top), ["rust", "golang", "go & rust"]);
+        let targets: Vec<&str> = top.edges().iter().map(|edge| edge.target.as_str()).collect();
+        assert_eq!(targets, ["golang", "go & rust"])
+    }
+
+    #[test]
+    fn export_graphml() {
+        let graph = graph().filtered(&GraphFilter::new().with_min_shows(30));
+        let mut output = vec![];
+
+
+        graph.write_graphml(&mut output).unwrap();
+
+
+        let received = String::from_utf8(output).unwrap();
+        assert!(received.contains(r#"<node id="n1"><data key="phrase">rust &quot;lang&quot;</data><data key="shows">50</data></node>"#));
+        assert!(received.contains(r#"<edge source="n0" target="n2"><data key="relation">searched_also</data><data key="edge_shows">80</data></edge>"#));
+        assert!(received.ends_with("</graph>\n</graphml>\n"))
+    }
+
+    #[test]
+    fn export_dot() {
+        let graph = graph().filtered(&GraphFilter::new().with_min_shows(30));
+        let mut output = vec![];
+
+
+        graph.write_dot(&mut output).unwrap();
+
+
+        let expected = "digraph keywords {\n\
+            \x20 n0 [label=\"rust\", shows=100];\n\
+            \x20 n1 [label=\"rust \\\"lang\\\"\", shows=50];\n\
+            \x20 n2 [label=\"golang\", shows=80];\n\
+            \x20 n0 -> n1 [relation=\"searched_with\", shows=50, style=solid];\n\
+            \x20 n0 -> n2 [relation=\"searched_also\", shows=80, style=dashed];\n\
+            }\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected)
+    }
+
+    #[test]
+    fn export_json() {
+        let graph = graph().filtered(&GraphFilter::new().with_top_neighbours(1));
+        let mut output = vec![];
+
+
+        graph.write_json(&mut output).unwrap();
+
+
+        let received: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(received["nodes"][0], json!({"id": "rust", "shows": 100}));
+        assert_eq!(received["links"][1], json!({"source": "golang", "target": "go & rust", "relation": "searched_with", "shows": 20}));
+        assert_eq!(received["links"].as_array().unwrap().len(), 2)
+    }
+}
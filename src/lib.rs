@@ -15,6 +15,26 @@
 //! ```
 //! This returns a [Result enum](Result), containing a [vector](Vec) of [regions](crate::region::Region)
 //!
+//! The regions can be arranged into a [RegionTree](crate::region_tree::RegionTree) to query
+//! their children, ancestors and descendants:
+//! ```rust,ignore
+//! let tree = RegionTree::new(regions).unwrap();
+//! let districts = tree.children(225);
+//! ```
+//!
+//! To find a region by a name typed by a human (in any case, with typos or transliterated)
+//! use the [RegionResolver](crate::region_resolver::RegionResolver):
+//! ```rust,ignore
+//! let moscow = RegionResolver::new(&regions).best("Moskva").unwrap();
+//! ```
+//!
+//! The region list rarely changes, so it can be kept on disk with a
+//! [RegionCache](crate::region_cache::RegionCache):
+//! ```rust,ignore
+//! let cache = RegionCache::new("regions.json", Duration::from_secs(24 * 60 * 60));
+//! let regions = cache.get_regions(&client).await.unwrap();
+//! ```
+//!
 //! To start generating a report you should craete a
 //! [ReportRequest](crate::create_report::ReportRequest) and then call
 //! [create_report](crate::create_report::create_report) function.
@@ -39,18 +59,104 @@
 //! This returns a [Result enum](Result), containing a [vector](Vec) of
 //! [report entries](crate::get_report::ReportEntry) (one per keyphrase in the ReportRequest).
 //!
+//! To wait for the report to be generated and get it as soon as it is ready use:
+//! ```rust,ignore
+//! let options = PollOptions::new().with_timeout(Duration::from_secs(600));
+//! let report = wait_for_report(&client, report_id, options).await;
+//! ```
+//!
 //! To delete a report you should use:
 //! ```rust,ignore
 //! delete_report(&client, 11053065).await.unwrap();
 //! ```
 //!
+//! Instead of calling these functions by hand you can let a
+//! [ReportManager](crate::report_manager::ReportManager) create, poll, download and
+//! delete the reports while keeping at most five of them on the server:
+//! ```rust,ignore
+//! let mut results = ReportManager::new(&client)
+//!     .with_requests(requests)
+//!     .run();
+//! while let Some(outcome) = results.next().await {
+//!     println!("{:?}", outcome.unwrap().result);
+//! }
+//! ```
+//!
+//! Reports for the same phrases and regions can be served from a
+//! [ReportCache](crate::report_cache::ReportCache) instead of spending the quota again:
+//! ```rust,ignore
+//! let reports = CachedReports::new(&client, FileReportCache::new("reports"));
+//! let entries = reports.get_report(&request).await.unwrap();
+//! ```
+//!
+//! ## Custom transports
+//!
+//! Requests are sent through a [Transport](crate::transport::Transport), which is
+//! [reqwest](crate::transport::ReqwestTransport) by default. You can supply your own
+//! implementation (another HTTP stack, a record/replay layer or an in-memory fake for tests):
+//! ```rust,ignore
+//! let client = Client::with_transport("token", "api_url", MyTransport::new());
+//! ```
+//!
+//! To keep reqwest but configure timeouts, a proxy, headers or a shared reqwest client,
+//! use the [ClientBuilder](crate::client_builder::ClientBuilder):
+//! ```rust,ignore
+//! let client = Client::builder("token", "api_url")
+//!     .with_timeout(Duration::from_secs(30))
+//!     .with_proxy("socks5://127.0.0.1:1080")
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! ## Errors
+//!
+//! All functions return a [WordstatError]. Errors reported by the API carry the
+//! [ApiError] with the raw `error_code`, `error_str` and `error_detail` fields, available via
+//! [api_error](WordstatError::api_error). Use [is_retryable](WordstatError::is_retryable),
+//! [is_auth](WordstatError::is_auth) and [is_quota](WordstatError::is_quota) to classify them.
+//!
+//! ## Retries
+//!
+//! Failed requests are not retried by default. Assign a [RetryPolicy](crate::retry::RetryPolicy)
+//! to retry transient errors (network failures, internal server errors, a full report queue)
+//! with exponential backoff:
+//! ```rust,ignore
+//! client.set_retry_policy(RetryPolicy::new().with_max_attempts(5));
+//! ```
+//! Report creation is not retried unless
+//! [allowed](crate::retry::RetryPolicy::with_retry_non_idempotent) explicitly,
+//! since a retried request could create a duplicate report.
+//!
+//! ## Cargo features
+//!
+//! - `serde` implements `Serialize` and `Deserialize` for the public data types
+//!   ([Region], [ReportStatus], [StatusCode], [ReportEntry], [WordstatItem] and [ReportRequest]),
+//!   using their field names as is. Wrap a value in [PascalCase](pascal_case::PascalCase)
+//!   to use the API's field names instead (`Phrase`, `Shows`, `GeoID`, `SearchedWith`...)
+//! - `bincode` allows storing the [RegionCache] in the bincode format instead of JSON
+//! - `sqlite` adds `SqliteStorage`, which keeps report runs and regions in an SQLite
+//!   database to track how the amount of searches changes over time
+//! - `csv` adds `write_csv` and `read_csv`, which flatten report entries into
+//!   spreadsheet friendly rows and back
+//! - `cli` builds the `wordstat` command-line tool (`regions`, `create`, `list`, `get`,
+//!   `delete` and `run` subcommands), enabling `csv` and `serde`
+//! - `mock-server` adds `MockServer`, a local stand-in for the API to run
+//!   end-to-end tests against
+//! - `blocking` adds the [blocking] module with a synchronous client for programs
+//!   without an async runtime
+//!
 //! ## Usage notes
 //!
 //! While using the library keep in mind:
-//! - One ReportRequest can contain up to 10 keyphrases
+//! - One ReportRequest can contain up to 10 keyphrases, use a
+//!   [BulkReportRequest](crate::bulk_report::BulkReportRequest) to split longer lists automatically
 //! - The server stores up to five reports simultaneously, so you should delete the report once you
-//! have downloaded its data
-//! - Geo is optional when creating a ReportRequest
+//!   have downloaded its data
+//! - Geo is optional when creating a ReportRequest, negative region IDs exclude regions.
+//!   The IDs are sent as is, call [validate_geo](crate::create_report::ReportRequest::validate_geo)
+//!   to check them against the region list before sending the request
+//! - Keyphrases are checked locally with the query language parser, see [Query](crate::query::Query),
+//!   so syntax errors like unbalanced parentheses are reported before any request is sent
 //!
 //! ## API URLs
 //!
@@ -62,65 +168,278 @@
 //! ## Getting the API token
 //!
 //! 1. Create an application that will be using the Yandex Direct API
-//! [here](https://oauth.yandex.ru/client/new)
+//!    [here](https://oauth.yandex.ru/client/new)
 //! 2. Recieve access to the API by filing the form 
-//! [here](https://direct.yandex.ru/registered/main.pl?cmd=apiCertificationRequestList)
+//!    [here](https://direct.yandex.ru/registered/main.pl?cmd=apiCertificationRequestList)
 //! 3. Turn on the sandbox mode 
-//! [here](https://direct.yandex.ru/registered/main.pl?cmd=apiApplicationList)
+//!    [here](https://direct.yandex.ru/registered/main.pl?cmd=apiApplicationList)
 //! 4. Get the token by authorizing in your app by following this link:
-//! <https://oauth.yandex.ru/authorize?response_type=token&client_id=[app_client_id]>
-//! Don't forget to replace the ```app_client_id``` with the client_id of your app.
+//!    <https://oauth.yandex.ru/authorize?response_type=token&client_id=[app_client_id]>
+//!    Don't forget to replace the ```app_client_id``` with the client_id of your app.
 
 pub mod region;
+pub mod region_tree;
+pub mod region_resolver;
+pub mod region_cache;
 pub mod client;
+pub mod client_builder;
 pub mod create_report;
+pub mod query;
+pub mod phrase;
+pub mod normalize;
 pub mod report_list;
 pub mod get_report;
 pub mod delete_report;
+pub mod transport;
+pub mod retry;
+pub mod clock;
+pub mod report_manager;
+pub mod wait_for_report;
+pub mod bulk_report;
+pub mod report_cache;
+pub mod keyword_graph;
+pub mod crawler;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "csv")]
+pub mod report_csv;
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock_server;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "serde")]
+pub mod pascal_case;
+mod response;
+mod file;
 
 pub use client::Client;
+pub use client_builder::ClientBuilder;
 pub use create_report::{ReportRequest, create_report};
+pub use query::{Query, Term, Word, parse_phrase};
+pub use phrase::{Phrase, ToPhrase};
+pub use normalize::{normalize_phrase, normalize_query};
 pub use delete_report::delete_report;
 pub use get_report::{ReportEntry, WordstatItem, get_report};
 pub use region::{Region, get_regions};
+pub use region_tree::RegionTree;
+pub use region_resolver::{RegionResolver, RegionMatch, MatchKind};
+pub use region_cache::{RegionCache, CacheFormat};
 pub use report_list::{ReportStatus, StatusCode, get_report_list};
+pub use report_manager::{ReportManager, ReportOutcome};
+pub use wait_for_report::{PollOptions, wait_for_report};
+pub use bulk_report::{BulkReportRequest, PhraseReport};
+pub use keyword_graph::{KeywordGraph, KeywordNode, KeywordEdge, Relation, GraphFilter};
+pub use crawler::KeywordCrawler;
+pub use report_cache::{ReportCache, CacheKey, CachedReport, MemoryReportCache, FileReportCache, CachedReports};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{SqliteStorage, ReportRun, ShowsSnapshot};
+#[cfg(feature = "csv")]
+pub use report_csv::{CsvOptions, write_csv, read_csv};
+#[cfg(feature = "mock-server")]
+pub use mock_server::MockServer;
+pub use tokio_util::sync::CancellationToken;
+pub use retry::RetryPolicy;
+pub use clock::{Clock, TokioClock, ManualClock};
+pub use transport::{Transport, TransportError, TransportErrorKind, ReqwestTransport};
 
 use custom_error::custom_error;
 use serde_json::Value;
 
+/// The error description returned by the API along with the error code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    /// The numeric error code (`error_code`)
+    pub code: i64,
+    /// Short description of the error (`error_str`)
+    pub message: String,
+    /// Details about the error, for example the invalid parameter (`error_detail`)
+    pub detail: String
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "code {}", self.code)?;
+        if !self.message.is_empty() { write!(f, ", {}", self.message)?; }
+        if !self.detail.is_empty() { write!(f, ": {}", self.detail)?; }
+        Ok(())
+    }
+}
+
+/// An error of another library wrapped into a [WordstatError], like the cause of a
+/// [Transport](WordstatError::Transport) or [Database](WordstatError::Database) error
+#[derive(Debug)]
+pub struct BoxedError(Box<dyn std::error::Error + Send + Sync>);
+
+impl BoxedError {
+    /// Wraps the error
+    pub fn new<E: std::error::Error + Send + Sync + 'static>(error: E) -> Self {
+        BoxedError(Box::new(error))
+    }
+
+    /// Returns the wrapped error
+    pub fn into_inner(self) -> Box<dyn std::error::Error + Send + Sync> {
+        self.0
+    }
+}
+
+impl std::fmt::Display for BoxedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for BoxedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// The underlying cause of a [Database](WordstatError::Database) error,
+/// as reported by the database driver
+#[cfg(feature = "sqlite")]
+pub type DatabaseError = BoxedError;
+
 custom_error!{pub WordstatError
-    BadResponse{reason: &'static str}               = "Response had bad structure",
-    BadKeyphrase{reason: &'static str}              = "Bad keyphrase supplied",
+    BadResponse{path: String, reason: String}       = "Response had bad structure at {path}: {reason}",
+    BadKeyphrase{reason: &'static str, position: usize}
+                                                    = "Bad keyphrase supplied: {reason} at position {position}",
     TooManyKeyphrases                               = "Too many keyphrases were supplied",
-    UnknownResponseCode{code:i64}                   = "Unknown response code recieved: {code}",
+    MismatchedPhraseMapping                         = "The phrase mapping does not match the phrases",
+    UnknownResponseCode{error: ApiError}            = "Unknown response code recieved ({error})",
+    HttpStatus{status: u16}                         = "Unexpected HTTP status recieved: {status}",
     UnknownError                                    = "Unknown error has occured",
-    ReportDoesNotExist                              = "The specified report does not exist",        // code 24, 91
-    InvalidReportId                                 = "The specified report ID is not valid",       // code 22, 93
-    ReportQueueFull                                 = "The report queue if full",                   // code 31
-    QuotaExhausted                                  = "The report quota has been exhausted",        // code 152
-    AuthorizationError                              = "Invalid login, token or token has expired",  // code 53
-    AccessDenied                                    = "Access to Yandex Direct API has been denied",// code 58
-    InternalServerError                             = "Internal server error",                      // code 500
-    InvalidRequest                                  = "The request was invalid",                    // code 501
-    ReportNotReady                                  = "The report is not ready yet",                // code 74, 92
-    InvalidRequestParameters                        = "The reqeust parameters were invalid"         // code 71
+    Transport{kind: TransportErrorKind, source: TransportError}
+                                                    = "Transport error while {kind}: {source}",
+    Timeout{kind: TransportErrorKind, source: TransportError}
+                                                    = "Timed out while {kind}",
+    ReportDoesNotExist{error: ApiError}             = "The specified report does not exist ({error})",        // code 24, 91
+    InvalidReportId{error: ApiError}                = "The specified report ID is not valid ({error})",       // code 22, 93
+    ReportQueueFull{error: ApiError}                = "The report queue if full ({error})",                   // code 31
+    QuotaExhausted{error: ApiError}                 = "The report quota has been exhausted ({error})",        // code 152
+    AuthorizationError{error: ApiError}             = "Invalid login, token or token has expired ({error})",  // code 53
+    AccessDenied{error: ApiError}                   = "Access to Yandex Direct API has been denied ({error})",// code 58
+    InternalServerError{error: ApiError}            = "Internal server error ({error})",                      // code 500
+    InvalidRequest{error: ApiError}                 = "The request was invalid ({error})",                    // code 501
+    ReportNotReady{error: ApiError}                 = "The report is not ready yet ({error})",                // code 74, 92
+    ReportNotFound{report_id: i64}                  = "The report {report_id} is not in the report list",
+    ReportFailed{report_id: i64}                    = "The report {report_id} has failed",
+    DeadlineExceeded{report_id: i64}                = "Timed out waiting for the report {report_id}",
+    Cancelled                                       = "The operation has been cancelled",
+    DuplicateRegion{id: i64}                        = "The region {id} is listed more than once",
+    OrphanedRegion{id: i64, parent_id: i64}         = "The parent {parent_id} of the region {id} does not exist",
+    RegionCycle{id: i64}                            = "The region {id} is its own ancestor",
+    UnknownRegion{id: i64}                          = "The region {id} does not exist",
+    RedundantRegion{id: i64, covered_by: i64}       = "The region {id} is already covered by {covered_by}",
+    InvalidClientConfig{reason: String}             = "Invalid client configuration: {reason}",
+    ManagerNotEmpty{requests: usize}                = "The report manager already has {requests} requests",
+    Io{source: std::io::Error}                      = "I/O error: {source}",
+    Database{source: BoxedError}                    = "Database error: {source}",
+    BadCsv{line: u64, reason: String}               = "Bad CSV at line {line}: {reason}",
+    UnsupportedSchema{version: i64}                 = "The database schema version {version} is newer than supported",
+    InvalidRequestParameters{error: ApiError}       = "The reqeust parameters were invalid ({error})"         // code 71
+}
+
+impl WordstatError {
+    /// Returns the error description sent by the API, if the error came from the API
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            WordstatError::UnknownResponseCode { error }
+            | WordstatError::ReportDoesNotExist { error }
+            | WordstatError::InvalidReportId { error }
+            | WordstatError::ReportQueueFull { error }
+            | WordstatError::QuotaExhausted { error }
+            | WordstatError::AuthorizationError { error }
+            | WordstatError::AccessDenied { error }
+            | WordstatError::InternalServerError { error }
+            | WordstatError::InvalidRequest { error }
+            | WordstatError::ReportNotReady { error }
+            | WordstatError::InvalidRequestParameters { error } => { Some(error) }
+            _ => { None }
+        }
+    }
+
+    /// Returns true if the same request may succeed later: network failures, timeouts,
+    /// 5xx HTTP statuses, internal server errors (code 500) and a full report queue (code 31).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            WordstatError::Transport { .. }             => { true }
+            WordstatError::Timeout { .. }               => { true }
+            WordstatError::InternalServerError { .. }   => { true }
+            WordstatError::ReportQueueFull { .. }       => { true }
+            WordstatError::HttpStatus { status }        => { (500..600).contains(status) }
+            _                                           => { false }
+        }
+    }
+
+    /// Returns true if the token is invalid or has no access to the API (codes 53 and 58).
+    pub fn is_auth(&self) -> bool {
+        matches!(self, WordstatError::AuthorizationError { .. } | WordstatError::AccessDenied { .. })
+    }
+
+    /// Returns true if the report quota has been exhausted (code 152).
+    pub fn is_quota(&self) -> bool {
+        matches!(self, WordstatError::QuotaExhausted { .. })
+    }
 }
 
 fn check_status(response: &Value) -> Result<(), WordstatError> {
-    let Some(error_code_val) = response.get("error_code") else { return Ok(()) };
-    let Some(error_code) = error_code_val.as_i64() else { return Err(WordstatError::BadResponse{ reason: "Error code is not an integer" } ) };
-
-    match error_code {
-        24 | 91     => { Err(WordstatError::ReportDoesNotExist) }
-        22 | 93     => { Err(WordstatError::InvalidReportId) }
-        31          => { Err(WordstatError::ReportQueueFull) }
-        152         => { Err(WordstatError::QuotaExhausted) }
-        53          => { Err(WordstatError::AuthorizationError) }
-        58          => { Err(WordstatError::AccessDenied) }
-        500         => { Err(WordstatError::InternalServerError) }
-        501         => { Err(WordstatError::InvalidRequest) }
-        74 | 92     => { Err(WordstatError::ReportNotReady) }
-        71          => { Err(WordstatError::InvalidRequestParameters) }
-        _           => { Err(WordstatError::UnknownResponseCode { code: error_code }) }
+    let response: response::ApiResponse<serde::de::IgnoredAny> = response::deserialize(response)?;
+    match response.error() {
+        Some(error) => { Err(error) }
+        None        => { Ok(()) }
+    }
+}
+
+fn error_from_api(error: ApiError) -> WordstatError {
+    match error.code {
+        24 | 91     => { WordstatError::ReportDoesNotExist { error } }
+        22 | 93     => { WordstatError::InvalidReportId { error } }
+        31          => { WordstatError::ReportQueueFull { error } }
+        152         => { WordstatError::QuotaExhausted { error } }
+        53          => { WordstatError::AuthorizationError { error } }
+        58          => { WordstatError::AccessDenied { error } }
+        500         => { WordstatError::InternalServerError { error } }
+        501         => { WordstatError::InvalidRequest { error } }
+        74 | 92     => { WordstatError::ReportNotReady { error } }
+        71          => { WordstatError::InvalidRequestParameters { error } }
+        _           => { WordstatError::UnknownResponseCode { error } }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(code: i64) -> ApiError {
+        ApiError { code, message: "Error".to_string(), detail: "".to_string() }
+    }
+
+    #[test]
+    fn classify_errors() {
+        let quota = error_from_api(api_error(152));
+        let auth = error_from_api(api_error(53));
+        let queue = error_from_api(api_error(31));
+
+
+        assert!(quota.is_quota() && !quota.is_auth() && !quota.is_retryable());
+        assert!(auth.is_auth() && !auth.is_quota() && !auth.is_retryable());
+        assert!(queue.is_retryable() && !queue.is_auth() && !queue.is_quota());
+        assert!(WordstatError::HttpStatus { status: 503 }.is_retryable());
+        assert!(!WordstatError::HttpStatus { status: 404 }.is_retryable())
+    }
+
+    #[test]
+    fn display_api_error() {
+        let error = ApiError {
+            code: 71,
+            message: "Invalid request parameters".to_string(),
+            detail: "Phrases must be an array".to_string()
+        };
+
+
+        let received = error_from_api(error).to_string();
+
+
+        assert_eq!(received, "The reqeust parameters were invalid (code 71, Invalid request parameters: Phrases must be an array)")
     }
 }
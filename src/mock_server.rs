@@ -0,0 +1,421 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use serde_json::{Value, json};
+use crate::create_report::MAX_PHRASES;
+use crate::get_report::{ReportEntry, ReportEntryModel, WordstatItem};
+use crate::region::Region;
+use crate::report_list::StatusCode;
+
+/// The amount of reports the server keeps at once
+const MAX_REPORTS: usize = 5;
+
+/// A local stand-in for the Wordstat API, serving the JSON API over HTTP on a random port.
+///
+/// It implements report creation, listing, downloading and deletion plus the region
+/// list, enforcing the 10 phrase and 5 report limits. Report statuses follow a
+/// [script](MockServer::with_script) and any method can be made to fail with an
+/// [injected](MockServer::inject_error) error code. The server stops when dropped.
+/// ```rust,ignore
+/// let server = MockServer::start().with_script(vec![StatusCode::Pending, StatusCode::Done]);
+/// let client = Client::new(&server.token(), &server.url());
+/// let report_id = create_report(&client, &request).await?;
+/// ```
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+    server: Arc<tiny_http::Server>,
+    thread: Option<JoinHandle<()>>
+}
+
+struct State {
+    token: String,
+    regions: Vec<Region>,
+    entries: HashMap<String, ReportEntry>,
+    script: Vec<StatusCode>,
+    reports: Vec<Report>,
+    next_report_id: i64,
+    errors: HashMap<String, VecDeque<i64>>,
+    calls: Vec<String>,
+    last_headers: HashMap<String, String>
+}
+
+struct Report {
+    id: i64,
+    phrases: Vec<String>,
+    geo_id: Vec<i64>,
+    /// The statuses left to go through, the last one stays
+    statuses: VecDeque<StatusCode>
+}
+
+impl Report {
+    fn status(&self) -> StatusCode {
+        self.statuses.front().copied().unwrap_or(StatusCode::Done)
+    }
+}
+
+impl MockServer {
+    /// Starts the server on a random local port. It accepts the token "token",
+    /// knows a few regions and finishes reports right away.
+    pub fn start() -> Self {
+        let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").expect("Failed to bind the mock server"));
+        let port = server.server_addr().to_ip().expect("The mock server listens on IP").port();
+        let state = Arc::new(Mutex::new(State {
+            token: "token".to_string(),
+            regions: vec![
+                Region { name: "Все".to_string(), id: 0, parent_id: None },
+                Region { name: "Россия".to_string(), id: 225, parent_id: Some(0) },
+                Region { name: "Москва и Московская область".to_string(), id: 1, parent_id: Some(225) },
+                Region { name: "Москва".to_string(), id: 213, parent_id: Some(1) },
+                Region { name: "Санкт-Петербург".to_string(), id: 2, parent_id: Some(225) },
+            ],
+            entries: HashMap::new(),
+            script: vec![StatusCode::Done],
+            reports: vec![],
+            next_report_id: 1,
+            errors: HashMap::new(),
+            calls: vec![],
+            last_headers: HashMap::new()
+        }));
+
+        let thread = {
+            let server = server.clone();
+            let state = state.clone();
+            std::thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let headers = request.headers().iter()
+                        .map(|header| (header.field.as_str().as_str().to_lowercase(), header.value.as_str().to_string()))
+                        .collect();
+                    let mut body = String::new();
+                    let response = match request.as_reader().read_to_string(&mut body) {
+                        Ok(_) => {
+                            let mut state = state.lock().unwrap();
+                            state.last_headers = headers;
+                            state.handle(&body)
+                        }
+                        Err(_) => { error(501, "Invalid request") }
+                    };
+                    let header = tiny_http::Header::from_bytes("Content-Type", "application/json; charset=utf-8").unwrap();
+                    let _ = request.respond(tiny_http::Response::from_string(response.to_string()).with_header(header));
+                }
+            })
+        };
+
+        MockServer { url: format!("http://127.0.0.1:{port}/"), state, server, thread: Some(thread) }
+    }
+
+    /// Returns the URL to pass to [Client::new](crate::client::Client::new)
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Returns the accepted token
+    pub fn token(&self) -> String {
+        self.state.lock().unwrap().token.clone()
+    }
+
+    /// Sets the accepted token, requests with other tokens fail with code 53
+    pub fn with_token(self, token: &str) -> Self {
+        self.state.lock().unwrap().token = token.to_string();
+        self
+    }
+
+    /// Sets the regions returned by GetRegions
+    pub fn with_regions(self, regions: Vec<Region>) -> Self {
+        self.state.lock().unwrap().regions = regions;
+        self
+    }
+
+    /// Sets the report entry returned for its phrase. Other phrases get an entry
+    /// with a single keyword with 100 shows.
+    pub fn with_entry(self, entry: ReportEntry) -> Self {
+        self.state.lock().unwrap().entries.insert(entry.phrase.clone(), entry);
+        self
+    }
+
+    /// Sets the statuses every new report goes through, one per GetWordstatReportList
+    /// call. The last status stays. Reports are [Done](StatusCode::Done) right away by default.
+    pub fn with_script(self, script: Vec<StatusCode>) -> Self {
+        self.state.lock().unwrap().script = script;
+        self
+    }
+
+    /// Makes the next call of the method fail with the error code. Calling it
+    /// several times queues the errors for the following calls.
+    pub fn inject_error(&self, method: &str, code: i64) {
+        self.state.lock().unwrap().errors.entry(method.to_string()).or_default().push_back(code);
+    }
+
+    /// Sets the status of an existing report, replacing the rest of its script
+    pub fn set_status(&self, report_id: i64, status: StatusCode) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(report) = state.reports.iter_mut().find(|report| report.id == report_id) {
+            report.statuses = VecDeque::from([status]);
+        }
+    }
+
+    /// Returns the IDs of the reports stored on the server
+    pub fn report_ids(&self) -> Vec<i64> {
+        self.state.lock().unwrap().reports.iter().map(|report| report.id).collect()
+    }
+
+    /// Returns the methods called so far, in order
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Returns the headers of the last request, with lowercase names
+    pub fn last_headers(&self) -> HashMap<String, String> {
+        self.state.lock().unwrap().last_headers.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+    }
+}
+
+impl State {
+    fn handle(&mut self, body: &str) -> Value {
+        let Ok(payload) = serde_json::from_str::<Value>(body) else { return error(501, "Invalid request") };
+        let method = payload["method"].as_str().unwrap_or_default().to_string();
+        self.calls.push(method.clone());
+
+        if payload["token"].as_str() != Some(self.token.as_str()) {
+            return error(53, "Authorization error");
+        }
+        if let Some(code) = self.errors.get_mut(&method).and_then(VecDeque::pop_front) {
+            return error(code, "Injected error");
+        }
+
+        let param = &payload["param"];
+        match method.as_str() {
+            "GetRegions" => {
+                let regions: Vec<Value> = self.regions.iter()
+                    .map(|region| json!({"RegionName": region.name, "RegionID": region.id, "ParentID": region.parent_id}))
+                    .collect();
+                json!({"data": regions})
+            }
+            "CreateNewWordstatReport" => { self.create_report(param) }
+            "GetWordstatReportList" => {
+                let reports: Vec<Value> = self.reports.iter_mut()
+                    .map(|report| {
+                        let status = report.status();
+                        if report.statuses.len() > 1 { report.statuses.pop_front(); }
+                        json!({"ReportID": report.id, "StatusReport": format!("{status:?}")})
+                    })
+                    .collect();
+                json!({"data": reports})
+            }
+            "GetWordstatReport" => { self.get_report(param) }
+            "DeleteWordstatReport" => {
+                let Some(position) = self.reports.iter().position(|report| Some(report.id) == param.as_i64()) else {
+                    return error(24, "Report does not exist");
+                };
+                self.reports.remove(position);
+                json!({"data": 1})
+            }
+            _ => { error(501, "Unknown method") }
+        }
+    }
+
+    fn create_report(&mut self, param: &Value) -> Value {
+        let phrases: Option<Vec<String>> = param["Phrases"].as_array()
+            .map(|phrases| phrases.iter().filter_map(|phrase| phrase.as_str().map(str::to_string)).collect());
+        let Some(phrases) = phrases.filter(|phrases| !phrases.is_empty()) else {
+            return error(71, "Invalid request parameters");
+        };
+        if phrases.len() > MAX_PHRASES {
+            return error(71, "Invalid request parameters");
+        }
+        if self.reports.len() >= MAX_REPORTS {
+            return error(31, "Report queue is full");
+        }
+
+        let geo_id = param["GeoID"].as_array()
+            .map(|geo_id| geo_id.iter().filter_map(Value::as_i64).collect())
+            .unwrap_or_default();
+        let id = self.next_report_id;
+        self.next_report_id += 1;
+        self.reports.push(Report { id, phrases, geo_id, statuses: self.script.iter().copied().collect() });
+        json!({"data": id})
+    }
+
+    fn get_report(&self, param: &Value) -> Value {
+        let Some(report) = self.reports.iter().find(|report| Some(report.id) == param.as_i64()) else {
+            return error(24, "Report does not exist");
+        };
+        match report.status() {
+            StatusCode::Done => {}
+            StatusCode::Failed => { return error(24, "Report does not exist"); }
+            _ => { return error(92, "Report is not ready yet"); }
+        }
+
+        let entries: Vec<ReportEntryModel> = report.phrases.iter()
+            .map(|phrase| {
+                let entry = self.entries.get(phrase).cloned().unwrap_or_else(|| ReportEntry {
+                    phrase: phrase.clone(),
+                    geo_id: vec![],
+                    searched_with: vec![WordstatItem { phrase: phrase.clone(), shows: 100 }],
+                    searched_also: vec![]
+                });
+                ReportEntryModel::from(&ReportEntry { geo_id: report.geo_id.clone(), ..entry })
+            })
+            .collect();
+        json!({"data": entries})
+    }
+}
+
+fn error(code: i64, message: &str) -> Value {
+    json!({"error_code": code, "error_str": message, "error_detail": ""})
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use futures::StreamExt;
+    use super::*;
+    use crate::WordstatError;
+    use crate::client::Client;
+    use crate::create_report::{ReportRequest, create_report};
+    use crate::delete_report::delete_report;
+    use crate::get_report::get_report;
+    use crate::region::get_regions;
+    use crate::report_list::get_report_list;
+    use crate::report_manager::ReportManager;
+    use crate::retry::RetryPolicy;
+    use crate::wait_for_report::{PollOptions, wait_for_report};
+
+    fn client(server: &MockServer) -> Client {
+        let mut client = Client::new(&server.token(), &server.url());
+        client.set_retry_policy(RetryPolicy::none());
+        client
+    }
+
+    fn request(phrases: &Vec<&str>) -> ReportRequest {
+        ReportRequest::new().with_phrases(phrases).unwrap().add_geo(213)
+    }
+
+    #[tokio::test]
+    async fn report_lifecycle() {
+        let server = MockServer::start();
+        let client = client(&server);
+
+
+        let report_id = create_report(&client, &request(&vec!["rust", "cargo"])).await.unwrap();
+        let statuses = get_report_list(&client).await.unwrap();
+        let report = get_report(&client, report_id).await.unwrap();
+        delete_report(&client, report_id).await.unwrap();
+
+
+        assert_eq!(statuses[0].status, StatusCode::Done);
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[1].phrase, "cargo");
+        assert_eq!(report[1].geo_id, vec![213]);
+        assert!(server.report_ids().is_empty());
+        assert!(matches!(get_report(&client, report_id).await, Err(WordstatError::ReportDoesNotExist { .. })))
+    }
+
+    #[tokio::test]
+    async fn regions() {
+        let server = MockServer::start();
+        let client = client(&server);
+
+
+        let received = get_regions(&client).await.unwrap();
+
+
+        assert_eq!(received[3], Region { name: "Москва".to_string(), id: 213, parent_id: Some(1) })
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let server = MockServer::start();
+        let client = client(&server);
+        let phrases: Vec<Value> = (0..11).map(|index| Value::from(format!("phrase {index}"))).collect();
+
+
+        let too_many_phrases = client.post("CreateNewWordstatReport", Some(json!({"Phrases": phrases, "GeoID": []}))).await;
+        for _ in 0..MAX_REPORTS {
+            create_report(&client, &request(&vec!["rust"])).await.unwrap();
+        }
+        let queue_full = create_report(&client, &request(&vec!["rust"])).await;
+
+
+        assert!(matches!(too_many_phrases, Err(WordstatError::InvalidRequestParameters { .. })));
+        assert!(matches!(queue_full, Err(WordstatError::ReportQueueFull { .. })))
+    }
+
+    #[tokio::test]
+    async fn injected_errors() {
+        let server = MockServer::start();
+        let client = client(&server);
+        server.inject_error("CreateNewWordstatReport", 152);
+        server.inject_error("GetWordstatReportList", 31);
+
+
+        let quota = create_report(&client, &request(&vec!["rust"])).await;
+        let queue = get_report_list(&client).await;
+        let unauthorized = get_regions(&Client::new("wrong", &server.url())).await;
+
+
+        assert!(matches!(quota, Err(WordstatError::QuotaExhausted { .. })));
+        assert!(matches!(queue, Err(WordstatError::ReportQueueFull { .. })));
+        assert!(matches!(unauthorized, Err(WordstatError::AuthorizationError { .. })));
+        assert!(get_report_list(&client).await.unwrap().is_empty())
+    }
+
+    #[tokio::test]
+    async fn scripted_statuses() {
+        let server = MockServer::start()
+            .with_script(vec![StatusCode::Pending, StatusCode::Pending, StatusCode::Done]);
+        let client = client(&server);
+        let report_id = create_report(&client, &request(&vec!["rust"])).await.unwrap();
+
+
+        let not_ready = get_report(&client, report_id).await;
+        let received = wait_for_report(&client, report_id, PollOptions::new().with_interval(Duration::from_millis(1))).await.unwrap();
+
+
+        assert!(matches!(not_ready, Err(WordstatError::ReportNotReady { .. })));
+        assert_eq!(received[0].phrase, "rust");
+        let polls = server.calls().iter().filter(|method| *method == "GetWordstatReportList").count();
+        assert_eq!(polls, 3)
+    }
+
+    #[tokio::test]
+    async fn failed_report() {
+        let server = MockServer::start().with_script(vec![StatusCode::Pending]);
+        let client = client(&server);
+        let report_id = create_report(&client, &request(&vec!["rust"])).await.unwrap();
+        server.set_status(report_id, StatusCode::Failed);
+
+
+        let received = wait_for_report(&client, report_id, PollOptions::new()).await;
+
+
+        assert!(matches!(received, Err(WordstatError::ReportFailed { .. })))
+    }
+
+    #[tokio::test]
+    async fn report_manager() {
+        let server = MockServer::start().with_script(vec![StatusCode::Pending, StatusCode::Done]);
+        let client = client(&server);
+        let requests: Vec<ReportRequest> = (0..8).map(|index| request(&vec![&format!("phrase {index}")])).collect();
+
+
+        let outcomes: Vec<_> = ReportManager::new(&client)
+            .with_requests(requests)
+            .with_poll_interval(Duration::from_millis(1))
+            .run()
+            .collect()
+            .await;
+
+
+        assert_eq!(outcomes.len(), 8);
+        assert!(outcomes.iter().all(|outcome| outcome.as_ref().unwrap().result.is_ok()));
+        assert!(server.report_ids().is_empty())
+    }
+}
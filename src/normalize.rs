@@ -0,0 +1,106 @@
+use crate::WordstatError;
+use crate::query::{Query, Term, Word, parse_phrase};
+
+/// Brings the phrase to a canonical form, so phrases that Wordstat treats the same
+/// way become equal strings:
+/// - words are lowercased and 'ё' is replaced with 'е'
+/// - extra whitespace is removed
+/// - words outside of fixed order groups are sorted
+/// - minus-words and minus-groups are deduplicated and moved to the end
+/// ```
+/// # use wordstat_rs::*;
+/// let received = normalize_phrase("  Ёлка  Купить -дешево -ДЁШЕВО").unwrap();
+///
+/// assert_eq!(received, "елка купить -дешево");
+/// ```
+pub fn normalize_phrase(phrase: &str) -> Result<String, WordstatError> {
+    Ok(normalize_query(&parse_phrase(phrase)?).to_string())
+}
+
+/// Same as [normalize_phrase] for an already parsed [Query]
+pub fn normalize_query(query: &Query) -> Query {
+    let mut terms = vec![];
+    let mut excluded = vec![];
+    let mut excluded_groups = vec![];
+    for term in &query.terms {
+        match term {
+            Term::Exclude(word) => { excluded.push(Term::Exclude(normalize_word(word))); }
+            Term::ExcludeGroup(words) => { excluded_groups.push(Term::ExcludeGroup(words.iter().map(normalize_word).collect())); }
+            term => { terms.push(normalize_term(term)); }
+        }
+    }
+    sort_terms(&mut terms);
+    sort_terms(&mut excluded);
+    excluded.dedup();
+    sort_terms(&mut excluded_groups);
+    excluded_groups.dedup();
+
+    terms.extend(excluded);
+    terms.extend(excluded_groups);
+    Query { terms }
+}
+
+fn normalize_term(term: &Term) -> Term {
+    match term {
+        Term::Word(word) => { Term::Word(normalize_word(word)) }
+        Term::Exclude(word) => { Term::Exclude(normalize_word(word)) }
+        Term::ExcludeGroup(words) => { Term::ExcludeGroup(words.iter().map(normalize_word).collect()) }
+        Term::FixedOrder(words) => { Term::FixedOrder(words.iter().map(normalize_word).collect()) }
+        Term::Quoted(terms) => { Term::Quoted(normalize_terms(terms)) }
+        Term::Group(alternatives) => {
+            let mut alternatives: Vec<Vec<Term>> = alternatives.iter().map(|terms| normalize_terms(terms)).collect();
+            alternatives.sort_by_cached_key(|terms| render(terms));
+            alternatives.dedup();
+            Term::Group(alternatives)
+        }
+    }
+}
+
+fn normalize_terms(terms: &[Term]) -> Vec<Term> {
+    let mut terms: Vec<Term> = terms.iter().map(normalize_term).collect();
+    sort_terms(&mut terms);
+    terms
+}
+
+fn normalize_word(word: &Word) -> Word {
+    Word { text: word.text.to_lowercase().replace('ё', "е"), exact_form: word.exact_form }
+}
+
+fn sort_terms(terms: &mut [Term]) {
+    terms.sort_by_cached_key(Term::to_string);
+}
+
+fn render(terms: &[Term]) -> String {
+    terms.iter().map(Term::to_string).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_phrases() {
+        let input = [
+            "купить ёлку -дешево -(своими руками)",
+            "  Купить   ЕЛКУ -(своими руками) -дешево -Дешево",
+            "ёлку купить -дешёво -(СВОИМИ руками) -(своими руками)",
+        ];
+
+
+        let received: Vec<String> = input.iter().map(|phrase| normalize_phrase(phrase).unwrap()).collect();
+
+
+        assert_eq!(received, vec!["елку купить -дешево -(своими руками)"; 3])
+    }
+
+    #[test]
+    fn keeps_operators() {
+        let input = "[New York] \"Hotel Cheap\" (Rent|buy) !Tickets";
+
+
+        let received = normalize_phrase(input).unwrap();
+
+
+        assert_eq!(received, "!tickets \"cheap hotel\" (buy|rent) [new york]")
+    }
+}
@@ -0,0 +1,172 @@
+//! The API's field names for the `serde` feature.
+//!
+//! The public data types are serialized with their own field names (`phrase`, `geo_id`...).
+//! Wrapping a value in [PascalCase] uses the names the API uses instead (`Phrase`, `GeoID`...),
+//! for example to store the data in the same shape it was received:
+//! ```
+//! # use wordstat_rs::Region;
+//! # use wordstat_rs::pascal_case::PascalCase;
+//! let regions = vec![Region { name: "Moscow".to_string(), id: 213, parent_id: Some(1) }];
+//!
+//! let json = serde_json::to_string(&PascalCase(&regions)).unwrap();
+//! assert_eq!(json, r#"[{"RegionName":"Moscow","RegionID":213,"ParentID":1}]"#);
+//!
+//! let PascalCase(received): PascalCase<Vec<Region>> = serde_json::from_str(&json).unwrap();
+//! assert_eq!(received, regions);
+//! ```
+//! [StatusCode](crate::StatusCode) is serialized the same way in both cases.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
+use crate::create_report::{PascalCaseRequestModel, ReportRequestModel};
+use crate::get_report::{ReportEntryModel, WordstatItemModel};
+use crate::region::RegionModel;
+use crate::report_list::ReportStatusModel;
+use crate::{Region, ReportEntry, ReportRequest, ReportStatus, WordstatItem};
+
+/// Serializes and deserializes the wrapped value with the API's field names
+#[derive(Debug, Clone, PartialEq)]
+pub struct PascalCase<T>(pub T);
+
+/// Types that can be serialized with the API's field names
+pub trait ToPascalCase {
+    fn serialize_pascal_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+}
+
+/// Types that can be deserialized from the API's field names
+pub trait FromPascalCase: Sized {
+    fn deserialize_pascal_case<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+impl<T: ToPascalCase> Serialize for PascalCase<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize_pascal_case(serializer)
+    }
+}
+
+impl<'de, T: FromPascalCase> Deserialize<'de> for PascalCase<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize_pascal_case(deserializer).map(PascalCase)
+    }
+}
+
+impl<T: ToPascalCase + ?Sized> ToPascalCase for &T {
+    fn serialize_pascal_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize_pascal_case(serializer)
+    }
+}
+
+impl<T: ToPascalCase> ToPascalCase for [T] {
+    fn serialize_pascal_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(PascalCase))
+    }
+}
+
+impl<T: ToPascalCase> ToPascalCase for Vec<T> {
+    fn serialize_pascal_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_slice().serialize_pascal_case(serializer)
+    }
+}
+
+impl<T: FromPascalCase> FromPascalCase for Vec<T> {
+    fn deserialize_pascal_case<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items = Vec::<PascalCase<T>>::deserialize(deserializer)?;
+        Ok(items.into_iter().map(|PascalCase(item)| item).collect())
+    }
+}
+
+/// Goes through the model the API responses are parsed with
+macro_rules! through_model {
+    ($type:ty, $model:ty) => {
+        impl ToPascalCase for $type {
+            fn serialize_pascal_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                <$model>::from(self).serialize(serializer)
+            }
+        }
+
+        impl FromPascalCase for $type {
+            fn deserialize_pascal_case<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <$model>::deserialize(deserializer).map(<$type>::from)
+            }
+        }
+    };
+}
+
+through_model!(Region, RegionModel);
+through_model!(ReportStatus, ReportStatusModel);
+through_model!(ReportEntry, ReportEntryModel);
+through_model!(WordstatItem, WordstatItemModel);
+
+impl ToPascalCase for ReportRequest {
+    fn serialize_pascal_case<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PascalCaseRequestModel::from(self).serialize(serializer)
+    }
+}
+
+impl FromPascalCase for ReportRequest {
+    fn deserialize_pascal_case<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let model = PascalCaseRequestModel::deserialize(deserializer)?;
+        ReportRequest::try_from(ReportRequestModel::from(model)).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StatusCode;
+
+    #[test]
+    fn entry_field_names() {
+        let entry = ReportEntry {
+            phrase: "rust-lang".to_string(),
+            geo_id: vec![213],
+            searched_with: vec![WordstatItem { phrase: "rust-lang how".to_string(), shows: 23 }],
+            searched_also: vec![]
+        };
+
+
+        let received = serde_json::to_value(PascalCase(&entry)).unwrap();
+
+
+        let expected = serde_json::json!({
+            "Phrase": "rust-lang",
+            "GeoID": [213],
+            "SearchedWith": [{"Phrase": "rust-lang how", "Shows": 23}],
+            "SearchedAlso": []
+        });
+        assert_eq!(received, expected)
+    }
+
+    #[test]
+    fn round_trip() {
+        let statuses = vec![
+            ReportStatus { report_id: 1, status: StatusCode::Done },
+            ReportStatus { report_id: 2, status: StatusCode::Unknown }
+        ];
+        let serialized = serde_json::to_value(PascalCase(&statuses)).unwrap();
+
+
+        let received: PascalCase<Vec<ReportStatus>> = serde_json::from_value(serialized.clone()).unwrap();
+
+
+        assert_eq!(serialized[0], serde_json::json!({"ReportID": 1, "StatusReport": "Done"}));
+        assert_eq!(received, PascalCase(statuses))
+    }
+
+    #[test]
+    fn request_is_validated() {
+        let request = ReportRequest::new()
+            .add_phrase("Rust  lang").unwrap()
+            .add_geo(213);
+        let serialized = serde_json::to_value(PascalCase(&request)).unwrap();
+        let mut mismatched = serialized.clone();
+        mismatched["Phrases"][0] = "cargo".into();
+
+
+        let PascalCase(received): PascalCase<ReportRequest> = serde_json::from_value(serialized).unwrap();
+
+
+        assert_eq!(received.phrase_mapping(), request.phrase_mapping());
+        assert_eq!(received.geo_id(), request.geo_id());
+        assert!(serde_json::from_value::<PascalCase<ReportRequest>>(mismatched).is_err())
+    }
+}
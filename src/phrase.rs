@@ -0,0 +1,181 @@
+use std::fmt;
+use crate::WordstatError;
+use crate::query::{Query, Term, Word, parse_phrase, write_terms};
+
+/// Builds a keyphrase from typed parts instead of formatting the operators by hand.
+///
+/// The phrase renders to Wordstat query syntax with [Display](fmt::Display) and is
+/// accepted directly by [ReportRequest::add_phrase](crate::create_report::ReportRequest::add_phrase).
+/// ```
+/// # use wordstat_rs::*;
+/// let phrase = Phrase::new()
+///     .word("buy")
+///     .exact_form("car")
+///     .fixed_order(&["new", "york"])
+///     .exclude("used")
+///     .exclude_group(&["diesel", "engine"]);
+///
+/// assert_eq!(phrase.to_string(), "buy !car [new york] -used -(diesel engine)");
+/// let request = ReportRequest::new().add_phrase(&phrase).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Phrase {
+    terms: Vec<Term>
+}
+
+impl Phrase {
+    /// Creates an empty phrase
+    pub fn new() -> Self {
+        Phrase { terms: vec![] }
+    }
+
+    /// Parses a phrase written in Wordstat query syntax
+    pub fn parse(phrase: &str) -> Result<Self, WordstatError> {
+        Ok(Phrase::from(parse_phrase(phrase)?))
+    }
+
+    /// Adds a word searched in any form
+    pub fn word(mut self, text: &str) -> Self {
+        self.terms.push(Term::Word(Word::new(text)));
+        self
+    }
+
+    /// Adds a word searched only in this form: `!word`
+    pub fn exact_form(mut self, text: &str) -> Self {
+        self.terms.push(Term::Word(Word::exact_form(text)));
+        self
+    }
+
+    /// Excludes queries containing the word: `-word`
+    pub fn exclude(mut self, text: &str) -> Self {
+        self.terms.push(Term::Exclude(Word::new(text)));
+        self
+    }
+
+    /// Excludes queries containing all of the words: `-(two words)`
+    pub fn exclude_group(mut self, words: &[&str]) -> Self {
+        self.terms.push(Term::ExcludeGroup(words.iter().map(|text| Word::new(text)).collect()));
+        self
+    }
+
+    /// Adds words that must follow in this order: `[two words]`
+    pub fn fixed_order(mut self, words: &[&str]) -> Self {
+        self.terms.push(Term::FixedOrder(words.iter().map(|text| Word::new(text)).collect()));
+        self
+    }
+
+    /// Adds words in quotes, limiting the queries to exactly these words: `"two words"`
+    pub fn quoted(mut self, words: &[&str]) -> Self {
+        self.terms.push(Term::Quoted(words.iter().map(|text| Term::Word(Word::new(text))).collect()));
+        self
+    }
+
+    /// Returns the parts of the phrase
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    /// Converts the phrase into a [Query], checking that it renders to valid syntax
+    /// which reads back into the same phrase. Returns [BadKeyphrase](WordstatError::BadKeyphrase)
+    /// if it does not, for example when a word contains whitespace or operators.
+    pub fn to_query(&self) -> Result<Query, WordstatError> {
+        let query = Query { terms: self.terms.clone() };
+        check_round_trip(&query)?;
+        Ok(query)
+    }
+}
+
+impl From<Query> for Phrase {
+    fn from(query: Query) -> Self {
+        Phrase { terms: query.terms }
+    }
+}
+
+impl fmt::Display for Phrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_terms(f, &self.terms)
+    }
+}
+
+/// Types that can be added to a [ReportRequest](crate::create_report::ReportRequest) as a keyphrase
+pub trait ToPhrase {
+    /// Returns the keyphrase in Wordstat query syntax, or
+    /// [BadKeyphrase](WordstatError::BadKeyphrase) if it is not valid.
+    fn to_phrase(&self) -> Result<String, WordstatError>;
+}
+
+impl ToPhrase for str {
+    fn to_phrase(&self) -> Result<String, WordstatError> {
+        parse_phrase(self)?;
+        Ok(self.to_string())
+    }
+}
+
+impl ToPhrase for String {
+    fn to_phrase(&self) -> Result<String, WordstatError> {
+        self.as_str().to_phrase()
+    }
+}
+
+impl ToPhrase for Query {
+    fn to_phrase(&self) -> Result<String, WordstatError> {
+        check_round_trip(self)
+    }
+}
+
+impl ToPhrase for Phrase {
+    fn to_phrase(&self) -> Result<String, WordstatError> {
+        Ok(self.to_query()?.to_string())
+    }
+}
+
+impl<T: ToPhrase + ?Sized> ToPhrase for &T {
+    fn to_phrase(&self) -> Result<String, WordstatError> {
+        (**self).to_phrase()
+    }
+}
+
+/// Renders the query and parses it back, so words containing operators or whitespace
+/// are rejected instead of silently changing the meaning of the phrase
+fn check_round_trip(query: &Query) -> Result<String, WordstatError> {
+    let rendered = query.to_string();
+    if parse_phrase(&rendered)? != *query {
+        return Err(WordstatError::BadKeyphrase { reason: "a word contains whitespace or operators", position: 0 });
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let phrase = Phrase::new()
+            .quoted(&["rust", "lang"])
+            .word("книга")
+            .exact_form("купить")
+            .fixed_order(&["санкт-петербург", "москва"])
+            .exclude("б.у")
+            .exclude_group(&["diesel", "engine"]);
+
+
+        let received = Phrase::parse(&phrase.to_phrase().unwrap()).unwrap();
+
+
+        assert_eq!(phrase.to_string(), "\"rust lang\" книга !купить [санкт-петербург москва] -б.у -(diesel engine)");
+        assert_eq!(received, phrase)
+    }
+
+    #[test]
+    fn invalid_words() {
+        let with_space = Phrase::new().word("two words");
+        let with_operator = Phrase::new().word("car").exclude("(diesel");
+        let only_minus_words = Phrase::new().exclude("car");
+
+
+        assert!(matches!(with_space.to_phrase(), Err(WordstatError::BadKeyphrase { .. })));
+        assert!(matches!(with_operator.to_phrase(), Err(WordstatError::BadKeyphrase { .. })));
+        assert!(matches!(only_minus_words.to_phrase(), Err(WordstatError::BadKeyphrase { .. })))
+    }
+}
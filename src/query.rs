@@ -0,0 +1,411 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::WordstatError;
+
+/// A keyphrase parsed from the Wordstat query language.
+///
+/// Supported operators:
+/// - `word` searches the word in any form
+/// - `!word` searches the exact word form
+/// - `-word` and `-!word` exclude queries containing the word
+/// - `-(two words)` excludes queries containing the whole group
+/// - `"two words"` limits the queries to exactly these words
+/// - `[two words]` fixes the order of the words
+/// - `(one|another)` searches either alternative
+///
+/// The characters `+`, `%`, `&` and `:` and a minus with spaces on both sides are not
+/// allowed, other punctuation is kept as a part of the word. Invalid phrases fail with
+/// [BadKeyphrase](WordstatError::BadKeyphrase), which carries the reason and the position
+/// of the first invalid character.
+/// ```
+/// # use wordstat_rs::*;
+/// let query: Query = "buy !car -(diesel engine)".parse().unwrap();
+///
+/// assert_eq!(query.terms[1], Term::Word(Word::exact_form("car")));
+/// assert!("buy (car|bike".parse::<Query>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    /// The terms in the order they appear in the phrase
+    pub terms: Vec<Term>
+}
+
+/// A part of a [Query]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// A word to search
+    Word(Word),
+    /// A minus-word: `-word`
+    Exclude(Word),
+    /// A minus-group: `-(two words)`
+    ExcludeGroup(Vec<Word>),
+    /// Words in quotes, limiting the queries to exactly these words: `"two words"`.
+    /// Contains only words and fixed order groups.
+    Quoted(Vec<Term>),
+    /// Words that must follow in this order: `[two words]`
+    FixedOrder(Vec<Word>),
+    /// Alternatives, any of which is searched: `(one|another)`.
+    /// Alternatives contain words, fixed order groups and nested alternatives.
+    Group(Vec<Vec<Term>>)
+}
+
+/// A single word of a [Query]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    /// The word itself
+    pub text: String,
+    /// True if only this form of the word is searched: `!word`
+    pub exact_form: bool
+}
+
+impl Word {
+    /// Creates a word searched in any form
+    pub fn new(text: &str) -> Self {
+        Word { text: text.to_string(), exact_form: false }
+    }
+
+    /// Creates a word searched in exactly this form
+    pub fn exact_form(text: &str) -> Self {
+        Word { text: text.to_string(), exact_form: true }
+    }
+}
+
+/// Parses the phrase, returning [BadKeyphrase](WordstatError::BadKeyphrase) with
+/// the character position of the first error.
+pub fn parse_phrase(phrase: &str) -> Result<Query, WordstatError> {
+    let mut parser = Parser { chars: phrase.chars().collect(), position: 0 };
+    let terms = parser.sequence(Context::Top)?;
+    if let Some(char) = parser.peek() {
+        let reason = match char {
+            ')' => { "unbalanced ')'" }
+            ']' => { "unbalanced ']'" }
+            _   => { "unexpected character" }
+        };
+        return Err(parser.error(reason));
+    }
+    if terms.is_empty() {
+        return Err(bad_keyphrase("the phrase is empty", 0));
+    }
+    if !terms.iter().any(|term| !matches!(term, Term::Exclude(_) | Term::ExcludeGroup(_))) {
+        return Err(bad_keyphrase("the phrase has only minus-words", 0));
+    }
+    Ok(Query { terms })
+}
+
+impl FromStr for Query {
+    type Err = WordstatError;
+
+    fn from_str(phrase: &str) -> Result<Self, Self::Err> {
+        parse_phrase(phrase)
+    }
+}
+
+impl fmt::Display for Query {
+    /// Writes the query in the canonical form, which parses back into the same query
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_terms(f, &self.terms)
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.exact_form { write!(f, "!")?; }
+        write!(f, "{}", self.text)
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Word(word) => { write!(f, "{word}") }
+            Term::Exclude(word) => { write!(f, "-{word}") }
+            Term::ExcludeGroup(words) => {
+                write!(f, "-(")?;
+                write_words(f, words)?;
+                write!(f, ")")
+            }
+            Term::Quoted(terms) => {
+                write!(f, "\"")?;
+                write_terms(f, terms)?;
+                write!(f, "\"")
+            }
+            Term::FixedOrder(words) => {
+                write!(f, "[")?;
+                write_words(f, words)?;
+                write!(f, "]")
+            }
+            Term::Group(alternatives) => {
+                write!(f, "(")?;
+                for (index, alternative) in alternatives.iter().enumerate() {
+                    if index > 0 { write!(f, "|")?; }
+                    write_terms(f, alternative)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+pub(crate) fn write_terms(f: &mut fmt::Formatter<'_>, terms: &[Term]) -> fmt::Result {
+    for (index, term) in terms.iter().enumerate() {
+        if index > 0 { write!(f, " ")?; }
+        write!(f, "{term}")?;
+    }
+    Ok(())
+}
+
+fn write_words(f: &mut fmt::Formatter<'_>, words: &[Word]) -> fmt::Result {
+    for (index, word) in words.iter().enumerate() {
+        if index > 0 { write!(f, " ")?; }
+        write!(f, "{word}")?;
+    }
+    Ok(())
+}
+
+/// Where the parsed sequence is, which decides the allowed terms and the closing character
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Top,
+    Quoted,
+    Group
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) { self.position += 1; }
+    }
+
+    fn error(&self, reason: &'static str) -> WordstatError {
+        bad_keyphrase(reason, self.position)
+    }
+
+    /// Parses terms until the end of the phrase or a character closing the context
+    fn sequence(&mut self, context: Context) -> Result<Vec<Term>, WordstatError> {
+        let mut terms = vec![];
+        loop {
+            self.skip_whitespace();
+            let Some(char) = self.peek() else { return Ok(terms) };
+            let term = match char {
+                ')' | ']' | '|' => { return Ok(terms); }
+                '"' if context == Context::Quoted => { return Ok(terms); }
+                '"' if context == Context::Group => { return Err(self.error("quotes can't be used inside a group")); }
+                '"' => { self.quoted()? }
+                '-' if context != Context::Top => { return Err(self.error("minus-words can only be used outside of quotes and groups")); }
+                '-' => { self.exclusion()? }
+                '[' => { Term::FixedOrder(self.fixed_order()?) }
+                '(' if context == Context::Quoted => { return Err(self.error("groups can't be used inside quotes")); }
+                '(' => { self.group()? }
+                _ => { Term::Word(self.word()?) }
+            };
+            terms.push(term);
+        }
+    }
+
+    fn quoted(&mut self) -> Result<Term, WordstatError> {
+        let start = self.position;
+        self.position += 1;
+        let terms = self.sequence(Context::Quoted)?;
+        match self.peek() {
+            Some('"') => { self.position += 1; }
+            Some(_) => { return Err(self.error("unexpected character inside quotes")); }
+            None => { return Err(bad_keyphrase("unclosed '\"'", start)); }
+        }
+        if terms.is_empty() { return Err(bad_keyphrase("empty quotes", start)); }
+        Ok(Term::Quoted(terms))
+    }
+
+    fn exclusion(&mut self) -> Result<Term, WordstatError> {
+        self.position += 1;
+        match self.peek() {
+            Some('(') => {
+                let start = self.position;
+                self.position += 1;
+                let words = self.words(')', start, "unclosed '('")?;
+                if words.is_empty() { return Err(bad_keyphrase("empty minus-group", start)); }
+                Ok(Term::ExcludeGroup(words))
+            }
+            Some(char) if is_word_char(char) || char == '!' => { Ok(Term::Exclude(self.word()?)) }
+            _ => { Err(self.error("'-' must be followed by a word or a group")) }
+        }
+    }
+
+    fn fixed_order(&mut self) -> Result<Vec<Word>, WordstatError> {
+        let start = self.position;
+        self.position += 1;
+        let words = self.words(']', start, "unclosed '['")?;
+        if words.is_empty() { return Err(bad_keyphrase("empty brackets", start)); }
+        Ok(words)
+    }
+
+    fn group(&mut self) -> Result<Term, WordstatError> {
+        let start = self.position;
+        self.position += 1;
+        let mut alternatives = vec![];
+        loop {
+            let alternative_start = self.position;
+            let alternative = self.sequence(Context::Group)?;
+            if alternative.is_empty() { return Err(bad_keyphrase("empty alternative", alternative_start)); }
+            alternatives.push(alternative);
+            match self.peek() {
+                Some('|') => { self.position += 1; }
+                Some(')') => {
+                    self.position += 1;
+                    return Ok(Term::Group(alternatives));
+                }
+                Some(_) => { return Err(self.error("unexpected character inside a group")); }
+                None => { return Err(bad_keyphrase("unclosed '('", start)); }
+            }
+        }
+    }
+
+    /// Parses plain and exact form words up to the closing character
+    fn words(&mut self, closing: char, start: usize, unclosed: &'static str) -> Result<Vec<Word>, WordstatError> {
+        let mut words = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(char) if char == closing => {
+                    self.position += 1;
+                    return Ok(words);
+                }
+                Some(char) if is_word_char(char) || char == '!' => { words.push(self.word()?); }
+                Some(_) => { return Err(self.error("only words can be used here")); }
+                None => { return Err(bad_keyphrase(unclosed, start)); }
+            }
+        }
+    }
+
+    fn word(&mut self) -> Result<Word, WordstatError> {
+        let exact_form = self.peek() == Some('!');
+        if exact_form { self.position += 1; }
+
+        let start = self.position;
+        while let Some(char) = self.peek() {
+            if is_word_char(char) || (char == '-' && self.position > start) {
+                self.position += 1;
+            }
+            else if char.is_whitespace() || "\"[]()|".contains(char) {
+                break;
+            }
+            else {
+                return Err(self.error(match char {
+                    '+' => { "'+' is not supported" }
+                    '&' => { "'&' is not supported" }
+                    '%' => { "'%' is not supported" }
+                    ':' => { "':' is not supported" }
+                    '!' => { "'!' must be at the start of a word" }
+                    _   => { "unsupported character" }
+                }));
+            }
+        }
+        if self.position == start { return Err(self.error("expected a word")); }
+
+        let text: String = self.chars[start..self.position].iter().collect();
+        if text.ends_with('-') { return Err(bad_keyphrase("a word can't end with '-'", self.position - 1)); }
+        Ok(Word { text, exact_form })
+    }
+}
+
+/// Anything but whitespace, the operators and the characters the API does not allow
+fn is_word_char(char: char) -> bool {
+    !char.is_whitespace() && !char.is_control() && !"!-\"[]()|+&%:".contains(char)
+}
+
+fn bad_keyphrase(reason: &'static str, position: usize) -> WordstatError {
+    WordstatError::BadKeyphrase { reason, position }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(texts: &[&str]) -> Vec<Word> {
+        texts.iter().map(|text| Word::new(text)).collect()
+    }
+
+    fn error(phrase: &str) -> (&'static str, usize) {
+        match parse_phrase(phrase) {
+            Err(WordstatError::BadKeyphrase { reason, position }) => { (reason, position) }
+            result => { panic!("Expected BadKeyphrase, got {result:?}") }
+        }
+    }
+
+    #[test]
+    fn parse_operators() {
+        let input = "купить !авто -(diesel engine) \"rust lang\" [to go] (car|bike [red one]) санкт-петербург -б.у";
+
+
+        let received = parse_phrase(input).unwrap();
+
+
+        let expected = Query { terms: vec![
+            Term::Word(Word::new("купить")),
+            Term::Word(Word::exact_form("авто")),
+            Term::ExcludeGroup(words(&["diesel", "engine"])),
+            Term::Quoted(vec![Term::Word(Word::new("rust")), Term::Word(Word::new("lang"))]),
+            Term::FixedOrder(words(&["to", "go"])),
+            Term::Group(vec![
+                vec![Term::Word(Word::new("car"))],
+                vec![Term::Word(Word::new("bike")), Term::FixedOrder(words(&["red", "one"]))],
+            ]),
+            Term::Word(Word::new("санкт-петербург")),
+            Term::Exclude(Word::new("б.у")),
+        ]};
+        assert_eq!(received, expected)
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error("car -(diesel engine) repair)"), ("unbalanced ')'", 27));
+        assert_eq!(error("car (diesel|petrol"), ("unclosed '('", 4));
+        assert_eq!(error("car \"diesel"), ("unclosed '\"'", 4));
+        assert_eq!(error("car - repair"), ("'-' must be followed by a word or a group", 5));
+        assert_eq!(error("car+repair"), ("'+' is not supported", 3));
+        assert_eq!(error("rust:lang"), ("':' is not supported", 4));
+        assert_eq!(error("(car|)"), ("empty alternative", 5));
+        assert_eq!(error("[car -repair]"), ("only words can be used here", 5));
+        assert_eq!(error("\"car -repair\""), ("minus-words can only be used outside of quotes and groups", 5));
+        assert_eq!(error("  "), ("the phrase is empty", 0));
+        assert_eq!(error("-car -bike"), ("the phrase has only minus-words", 0))
+    }
+
+    #[test]
+    fn render_canonical_form() {
+        let input = "  buy   !car  -( diesel  engine ) \" rust [ to go ] \" ( a | b c ) ";
+
+
+        let received = parse_phrase(input).unwrap().to_string();
+
+
+        assert_eq!(received, "buy !car -(diesel engine) \"rust [to go]\" (a|b c)");
+        assert_eq!(parse_phrase(&received).unwrap(), parse_phrase(input).unwrap())
+    }
+
+    #[test]
+    fn parse_punctuation() {
+        let input = "c#, what's 1/2? rust-lang.org @home -price$";
+
+
+        let received = parse_phrase(input).unwrap();
+
+
+        let expected = Query { terms: vec![
+            Term::Word(Word::new("c#,")),
+            Term::Word(Word::new("what's")),
+            Term::Word(Word::new("1/2?")),
+            Term::Word(Word::new("rust-lang.org")),
+            Term::Word(Word::new("@home")),
+            Term::Exclude(Word::new("price$"))
+        ]};
+        assert_eq!(received, expected)
+    }
+}
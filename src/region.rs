@@ -1,12 +1,11 @@
-use serde_json::Value;
-use crate::{WordstatError, check_status};
-use mockall_double::double;
-#[double] // For mocking the client in unit tests
+use serde::{Deserialize, Serialize};
+use crate::WordstatError;
 use crate::client::Client;
 
 
 /// Struct describing a region
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Region {
     /// The name of the region
     pub name: String,
@@ -16,45 +15,44 @@ pub struct Region {
     pub parent_id: Option<i64>,
 }
 
+pub(crate) const METHOD: &str = "GetRegions";
+
 /// Sends a request to the API asking for a list of regions
 pub async fn get_regions(client: &Client) -> Result<Vec<Region>, WordstatError> {
-    let method = "GetRegions";
-    let result = client.post(method, None).await?;
-
-    check_status(&result)?;
+    let regions: Vec<RegionModel> = client.call(METHOD, None).await?;
 
-    let Some(data) = result.get("data") else { return Err(WordstatError::BadResponse{ reason: "No data field in response" }) };
-    let Value::Array(regions) = data else { return Err(WordstatError::BadResponse{ reason: "Data field does not contain an array" }) };
-
-    parse_data(&regions)
+    Ok(regions.into_iter().map(Region::from).collect())
 }
 
-fn parse_data(data: &Vec<Value>) -> Result<Vec<Region>, WordstatError> {
-    let mut regions: Vec<Region> = vec![];
+/// A region as it is returned by the API
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RegionModel {
+    #[serde(rename = "RegionName")]
+    name: String,
+    #[serde(rename = "RegionID")]
+    id: i64,
+    #[serde(rename = "ParentID")]
+    parent_id: Option<i64>
+}
 
-    for reg in data {
-        regions.push(parse_region(reg)?);
+impl From<&Region> for RegionModel {
+    fn from(region: &Region) -> Self {
+        RegionModel {
+            name: region.name.clone(),
+            id: region.id,
+            parent_id: region.parent_id
+        }
     }
-
-    Ok(regions)
 }
 
-fn parse_region(reg: &Value) -> Result<Region, WordstatError> {
-    let Some(name_val) = reg.get("RegionName") else { return Err(WordstatError::BadResponse{ reason: "No RegionName field" }) };
-    let Value::String(name) = name_val else { return Err(WordstatError::BadResponse{ reason: "RegionName field is not a string" }) };
-    let Some(parent_id_val) = reg.get("ParentID") else { return Err(WordstatError::BadResponse{ reason: "No ParentID field" }) };
-    let parent_id: Option<i64> = match parent_id_val {
-        Value::Null => { None }
-        Value::Number(num) => { Some(num.as_i64().unwrap()) } // Unsafe but unlikely to panic
-        _ => { return Err(WordstatError::BadResponse{ reason: "ParentID field is not null and not a number" }); }
-    };
-    let Some(id_val) = reg.get("RegionID") else { return Err(WordstatError::BadResponse{ reason: "No RegionID field" }) };
-    let Some(id) = id_val.as_i64() else { return Err(WordstatError::BadResponse{ reason: "RegionID is not an integer" }) };
-
-    Ok(Region {
-        name: name.clone(),
-        id, parent_id
-    })
+impl From<RegionModel> for Region {
+    fn from(model: RegionModel) -> Self {
+        Region {
+            name: model.name,
+            id: model.id,
+            parent_id: model.parent_id
+        }
+    }
 }
 
 
@@ -62,6 +60,9 @@ fn parse_region(reg: &Value) -> Result<Region, WordstatError> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::Value;
+    use crate::response::deserialize;
+    use crate::transport::MockTransport;
 
     #[test]
     fn parse_region_europe() {
@@ -71,7 +72,7 @@ mod tests {
         let input: Value = serde_json::from_str(data).unwrap();
 
 
-        let received = parse_region(&input).unwrap();
+        let received = Region::from(deserialize::<RegionModel, _>(&input).unwrap());
 
 
         let expected = Region {
@@ -90,7 +91,7 @@ mod tests {
         let input: Value = serde_json::from_str(data).unwrap();
 
 
-        let received = parse_region(&input).unwrap();
+        let received = Region::from(deserialize::<RegionModel, _>(&input).unwrap());
 
 
         let expected = Region {
@@ -116,10 +117,12 @@ mod tests {
                     ]
             "#;
         let input: Value = serde_json::from_str(data).unwrap();
-        let Value::Array(input_vec) = input else { panic!("Something wrong with serde") };
 
 
-        let received = parse_data(&input_vec).unwrap();
+        let received: Vec<Region> = deserialize::<Vec<RegionModel>, _>(&input).unwrap()
+            .into_iter()
+            .map(Region::from)
+            .collect();
 
 
         let expected = vec![
@@ -154,13 +157,14 @@ mod tests {
             "#;
         let return_value = serde_json::from_str(data).unwrap();
 
-        let mut mock_client = Client::default();
-        mock_client.expect_post()
-            .withf(|method, _params| method == "GetRegions")
-            .return_once(move |_method, _params| Ok(return_value));
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .withf(|_url, payload| payload["method"] == "GetRegions")
+            .return_once(move |_url, _payload| Ok(return_value));
+        let client = Client::with_transport("token", "api_url", transport);
 
 
-        let received = futures::executor::block_on(super::get_regions(&mock_client)).unwrap();
+        let received = futures::executor::block_on(super::get_regions(&client)).unwrap();
 
 
         let expected = vec![
@@ -175,4 +179,19 @@ mod tests {
             Region { name: "North America".to_string(), id: 10002, parent_id: Some(0) }];
         assert_eq!(received, expected)
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        let regions = vec![
+            Region { name: "All".to_string(), id: 0, parent_id: None },
+            Region { name: "Europe".to_string(), id: 111, parent_id: Some(0) }];
+
+
+        let serialized = serde_json::to_string(&regions).unwrap();
+        let received: Vec<Region> = serde_json::from_str(&serialized).unwrap();
+
+
+        assert_eq!(received, regions)
+    }
 }
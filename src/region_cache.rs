@@ -0,0 +1,331 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::WordstatError;
+use crate::client::Client;
+use crate::region::{Region, get_regions};
+use crate::file::write_atomically;
+
+/// The format of the cache file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheFormat {
+    /// Human readable JSON
+    #[default]
+    Json,
+    /// Compact binary format, requires the `bincode` feature
+    #[cfg(feature = "bincode")]
+    Bincode
+}
+
+/// Keeps the region list in a file, so that [get_regions] is not called
+/// every time the regions are needed.
+///
+/// The cached list is served while it is younger than the TTL. A stale list is
+/// still returned if the API fails with a quota, server or network error.
+/// The file is read and written with [tokio::fs], so the cache needs a tokio runtime.
+/// ```rust,ignore
+/// let cache = RegionCache::new("regions.json", Duration::from_secs(7 * 24 * 60 * 60));
+/// let regions = cache.get_regions(&client).await?;
+/// ```
+#[derive(Debug)]
+pub struct RegionCache {
+    path: PathBuf,
+    ttl: Duration,
+    format: CacheFormat,
+    refreshing: AtomicBool,
+    store_error: Mutex<Option<WordstatError>>
+}
+
+/// The contents of the cache file
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    /// Seconds since the Unix epoch
+    fetched_at: u64,
+    regions: Vec<CachedRegion>
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedRegion {
+    name: String,
+    id: i64,
+    parent_id: Option<i64>
+}
+
+impl RegionCache {
+    /// Creates a JSON cache stored in the passed file, treating the list as fresh for `ttl`
+    pub fn new<P: Into<PathBuf>>(path: P, ttl: Duration) -> Self {
+        RegionCache {
+            path: path.into(),
+            ttl,
+            format: CacheFormat::Json,
+            refreshing: AtomicBool::new(false),
+            store_error: Mutex::new(None)
+        }
+    }
+
+    /// Sets the format of the cache file. A file in another format is treated as missing.
+    pub fn with_format(mut self, format: CacheFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Returns the error of the last failed attempt to write the cache file, clearing it.
+    /// Failing to write the file doesn't fail [refresh](RegionCache::refresh), including
+    /// the refreshes started by [get_regions_in_background](RegionCache::get_regions_in_background).
+    pub fn take_store_error(&self) -> Option<WordstatError> {
+        self.store_error.lock().unwrap().take()
+    }
+
+    /// Returns the cached regions if they are fresh. Otherwise downloads them
+    /// with [get_regions] and updates the cache, falling back to the stale list
+    /// if the API is unavailable.
+    pub async fn get_regions(&self, client: &Client) -> Result<Vec<Region>, WordstatError> {
+        let cached = self.load().await;
+        if let Some((regions, true)) = cached {
+            return Ok(regions);
+        }
+
+        match self.refresh(client).await {
+            Ok(regions) => { Ok(regions) }
+            Err(error) if can_use_stale(&error) => {
+                match cached {
+                    Some((regions, _)) => { Ok(regions) }
+                    None => { Err(error) }
+                }
+            }
+            Err(error) => { Err(error) }
+        }
+    }
+
+    /// Same as [get_regions](RegionCache::get_regions), but if the cached list is stale
+    /// it is returned right away and refreshed in a background tokio task.
+    pub async fn get_regions_in_background(self: &Arc<Self>, client: &Arc<Client>) -> Result<Vec<Region>, WordstatError> {
+        let Some((regions, fresh)) = self.load().await else { return self.get_regions(client).await };
+
+        if !fresh && !self.refreshing.swap(true, Ordering::SeqCst) {
+            let cache = self.clone();
+            let client = client.clone();
+            tokio::spawn(async move {
+                // The stale list is still usable, so a failed refresh is retried on the next call
+                let _ = cache.refresh(&client).await;
+                cache.refreshing.store(false, Ordering::SeqCst);
+            });
+        }
+
+        Ok(regions)
+    }
+
+    /// Downloads the regions and stores them in the cache file.
+    /// The downloaded regions are returned even if the cache file can not be written,
+    /// see [take_store_error](RegionCache::take_store_error).
+    pub async fn refresh(&self, client: &Client) -> Result<Vec<Region>, WordstatError> {
+        let regions = get_regions(client).await?;
+        // A cache that can not be written only means the regions are downloaded again next time
+        if let Err(error) = self.store(&regions).await {
+            *self.store_error.lock().unwrap() = Some(error);
+        }
+        Ok(regions)
+    }
+
+    /// Reads the cache file. Returns the regions and whether they are still fresh,
+    /// or None if there is no readable cache.
+    async fn load(&self) -> Option<(Vec<Region>, bool)> {
+        let contents = tokio::fs::read(&self.path).await.ok()?;
+        let file: CacheFile = match self.format {
+            CacheFormat::Json => { serde_json::from_slice(&contents).ok()? }
+            #[cfg(feature = "bincode")]
+            CacheFormat::Bincode => { bincode::deserialize(&contents).ok()? }
+        };
+        let age = unix_time().saturating_sub(file.fetched_at);
+        let regions = file.regions.into_iter()
+            .map(|region| Region { name: region.name, id: region.id, parent_id: region.parent_id })
+            .collect();
+        Some((regions, age < self.ttl.as_secs()))
+    }
+
+    async fn store(&self, regions: &[Region]) -> Result<(), WordstatError> {
+        let file = CacheFile {
+            fetched_at: unix_time(),
+            regions: regions.iter()
+                .map(|region| CachedRegion { name: region.name.clone(), id: region.id, parent_id: region.parent_id })
+                .collect()
+        };
+        let contents = match self.format {
+            CacheFormat::Json => { serde_json::to_vec(&file).expect("Region list is always serializable") }
+            #[cfg(feature = "bincode")]
+            CacheFormat::Bincode => { bincode::serialize(&file).expect("Region list is always serializable") }
+        };
+
+        write_atomically(&self.path, contents).await?;
+        Ok(())
+    }
+}
+
+/// Returns true if the error means that the API is unavailable for now,
+/// so the stale regions are better than nothing
+fn can_use_stale(error: &WordstatError) -> bool {
+    matches!(error,
+        WordstatError::QuotaExhausted { .. }
+        | WordstatError::InternalServerError { .. }
+        | WordstatError::Transport { .. }
+        | WordstatError::Timeout { .. })
+        || matches!(error, WordstatError::HttpStatus { status } if (500..600).contains(status))
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::transport::MockTransport;
+
+    const REGIONS: &str = r#"{"data": [{"ParentID": null, "RegionName": "All", "RegionID": 0}]}"#;
+
+    fn client(response: &'static str, times: usize) -> Client {
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .times(times)
+            .returning(move |_url, _payload| Ok(serde_json::from_str(response).unwrap()));
+        Client::with_transport("token", "api_url", transport)
+    }
+
+    fn write_cache(path: &std::path::Path, age: u64) {
+        let file = json!({
+            "fetched_at": unix_time() - age,
+            "regions": [{"name": "Europe", "id": 111, "parent_id": 0}]
+        });
+        std::fs::write(path, file.to_string()).unwrap();
+    }
+
+    fn names(regions: Vec<Region>) -> Vec<String> {
+        regions.into_iter().map(|region| region.name).collect()
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("regions.json");
+        write_cache(&path, 10);
+        let cache = RegionCache::new(&path, Duration::from_secs(60));
+
+
+        let received = cache.get_regions(&client(REGIONS, 0)).await.unwrap();
+
+
+        assert_eq!(names(received), vec!["Europe"])
+    }
+
+    #[tokio::test]
+    async fn refreshes_stale_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("regions.json");
+        write_cache(&path, 100);
+        let cache = RegionCache::new(&path, Duration::from_secs(60));
+
+
+        let received = cache.get_regions(&client(REGIONS, 1)).await.unwrap();
+
+
+        assert_eq!(names(received), vec!["All"]);
+        let cached = cache.get_regions(&client(REGIONS, 0)).await.unwrap();
+        assert_eq!(names(cached), vec!["All"])
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_stale_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("regions.json");
+        write_cache(&path, 100);
+        let cache = RegionCache::new(&path, Duration::from_secs(60));
+
+
+        let received = cache.get_regions(&client(r#"{"error_code": 152}"#, 1)).await.unwrap();
+
+
+        assert_eq!(names(received), vec!["Europe"])
+    }
+
+    #[tokio::test]
+    async fn fails_without_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RegionCache::new(dir.path().join("regions.json"), Duration::from_secs(60));
+
+
+        let received = cache.get_regions(&client(r#"{"error_code": 500}"#, 1)).await;
+
+
+        assert!(matches!(received, Err(WordstatError::InternalServerError { .. })))
+    }
+
+    #[tokio::test]
+    async fn falls_back_on_http_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("regions.json");
+        write_cache(&path, 100);
+        let cache = RegionCache::new(&path, Duration::from_secs(60));
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .returning(|_url, _payload| Err(WordstatError::HttpStatus { status: 502 }));
+
+
+        let received = cache.get_regions(&Client::with_transport("token", "api_url", transport)).await.unwrap();
+
+
+        assert_eq!(names(received), vec!["Europe"])
+    }
+
+    #[tokio::test]
+    async fn ignores_unwritable_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RegionCache::new(dir.path().join("missing").join("regions.json"), Duration::from_secs(60));
+
+
+        let received = cache.get_regions(&client(REGIONS, 1)).await.unwrap();
+
+
+        assert_eq!(names(received), vec!["All"]);
+        assert!(matches!(cache.take_store_error(), Some(WordstatError::Io { .. })));
+        assert!(cache.take_store_error().is_none())
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn bincode_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RegionCache::new(dir.path().join("regions.bin"), Duration::from_secs(60))
+            .with_format(CacheFormat::Bincode);
+        cache.get_regions(&client(REGIONS, 1)).await.unwrap();
+
+
+        let received = cache.get_regions(&client(REGIONS, 0)).await.unwrap();
+
+
+        assert_eq!(names(received), vec!["All"])
+    }
+
+    #[tokio::test]
+    async fn refreshes_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("regions.json");
+        write_cache(&path, 100);
+        let cache = Arc::new(RegionCache::new(&path, Duration::from_secs(60)));
+        let client = Arc::new(client(REGIONS, 1));
+
+
+        let received = cache.get_regions_in_background(&client).await.unwrap();
+
+
+        assert_eq!(names(received), vec!["Europe"]);
+        while cache.refreshing.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+        let (refreshed, fresh) = cache.load().await.unwrap();
+        assert_eq!(names(refreshed), vec!["All"]);
+        assert!(fresh)
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use crate::region::Region;
+
+/// How a region name matched the query, from the most to the least reliable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// The name is exactly the query
+    Exact,
+    /// The name differs from the query only in letter case or 'ё'/'е'
+    CaseInsensitive,
+    /// The name matches the query once both are transliterated to Latin
+    Transliterated,
+    /// The name starts with the query
+    Prefix,
+    /// The name is within the allowed edit distance from the query
+    Fuzzy
+}
+
+/// A region found by the [RegionResolver]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionMatch<'a> {
+    /// The matched region
+    pub region: &'a Region,
+    /// How the region matched the query
+    pub kind: MatchKind,
+    /// The edit distance between the query and the name, 0 unless the match is [Fuzzy](MatchKind::Fuzzy)
+    pub distance: usize
+}
+
+/// Finds regions by human input, so that "Москва", "москва" and "Moskva" all
+/// resolve to the same region.
+///
+/// The names returned by [get_regions](crate::region::get_regions) are usually in Russian.
+/// Transliteration covers romanized spellings like "Moskva", and the English names
+/// of major regions like "Moscow" are built in. Other names can be added as
+/// [aliases](RegionResolver::with_alias).
+/// ```
+/// # use wordstat_rs::*;
+/// let regions = vec![
+///     Region { name: "Москва".to_string(), id: 213, parent_id: Some(1) },
+///     Region { name: "Зеленоград".to_string(), id: 216, parent_id: Some(1) },
+/// ];
+/// let resolver = RegionResolver::new(&regions)
+///     .with_alias("Zelenograd city", 216);
+///
+/// assert_eq!(resolver.best("Moskva").unwrap().id, 213);
+/// assert_eq!(resolver.best("moscow").unwrap().id, 213);
+/// assert_eq!(resolver.best("zelenograd city").unwrap().id, 216);
+/// ```
+#[derive(Debug)]
+pub struct RegionResolver<'a> {
+    regions: &'a [Region],
+    aliases: HashMap<i64, Vec<String>>,
+    max_distance: usize
+}
+
+impl<'a> RegionResolver<'a> {
+    /// Creates a resolver over the passed regions, allowing fuzzy matches
+    /// within 2 edits.
+    pub fn new(regions: &'a [Region]) -> Self {
+        RegionResolver { regions, aliases: HashMap::new(), max_distance: 2 }
+    }
+
+    /// Adds another name the region can be found by.
+    pub fn with_alias(mut self, alias: &str, region_id: i64) -> Self {
+        self.aliases.entry(region_id).or_default().push(alias.to_string());
+        self
+    }
+
+    /// Sets the maximum edit distance for [Fuzzy](MatchKind::Fuzzy) matches.
+    /// 0 disables fuzzy matching.
+    pub fn with_max_distance(mut self, max_distance: usize) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    /// Returns the regions matching the query, best matches first
+    pub fn resolve(&self, query: &str) -> Vec<RegionMatch<'a>> {
+        let query = query.trim();
+        if query.is_empty() { return vec![]; }
+        let folded_query = fold_case(query);
+        let latin_query = transliterate(&folded_query);
+
+        let mut matches: Vec<RegionMatch<'a>> = self.regions.iter()
+            .filter_map(|region| {
+                let aliases = self.aliases.get(&region.id).map(Vec::as_slice).unwrap_or_default();
+                std::iter::once(region.name.as_str())
+                    .chain(aliases.iter().map(String::as_str))
+                    .chain(builtin_aliases(&region.name))
+                    .filter_map(|name| self.match_name(name, query, &folded_query, &latin_query))
+                    .min()
+                    .map(|(kind, distance)| RegionMatch { region, kind, distance })
+            })
+            .collect();
+        matches.sort_by(|first, second| {
+            (first.kind, first.distance, first.region.name.chars().count(), first.region.id)
+                .cmp(&(second.kind, second.distance, second.region.name.chars().count(), second.region.id))
+        });
+        matches
+    }
+
+    /// Returns the best matching region
+    pub fn best(&self, query: &str) -> Option<&'a Region> {
+        self.resolve(query).first().map(|found| found.region)
+    }
+
+    fn match_name(&self, name: &str, query: &str, folded_query: &str, latin_query: &str) -> Option<(MatchKind, usize)> {
+        if name == query { return Some((MatchKind::Exact, 0)); }
+        let folded_name = fold_case(name);
+        if folded_name == folded_query { return Some((MatchKind::CaseInsensitive, 0)); }
+        let latin_name = transliterate(&folded_name);
+        if latin_name == latin_query { return Some((MatchKind::Transliterated, 0)); }
+        if latin_name.starts_with(latin_query) { return Some((MatchKind::Prefix, 0)); }
+        let distance = edit_distance(&latin_name, latin_query);
+        if distance <= self.max_distance { return Some((MatchKind::Fuzzy, distance)); }
+        None
+    }
+}
+
+/// English names of major regions, keyed by the folded Russian name
+const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("россия", "Russia"),
+    ("москва", "Moscow"),
+    ("москва и московская область", "Moscow and Moscow Oblast"),
+    ("санкт-петербург", "Saint Petersburg"),
+    ("санкт-петербург", "St. Petersburg"),
+    ("санкт-петербург и ленинградская область", "Saint Petersburg and Leningrad Oblast"),
+    ("нижний новгород", "Nizhny Novgorod"),
+    ("ростов-на-дону", "Rostov-on-Don"),
+    ("киев", "Kyiv"),
+    ("киев", "Kiev"),
+    ("украина", "Ukraine"),
+    ("беларусь", "Belarus"),
+    ("казахстан", "Kazakhstan"),
+    ("снг", "CIS"),
+    ("европа", "Europe"),
+    ("азия", "Asia"),
+];
+
+/// Returns the built-in English names of the region
+fn builtin_aliases<'n>(name: &str) -> impl Iterator<Item = &'n str> {
+    let name = fold_case(name);
+    BUILTIN_ALIASES.iter()
+        .filter(move |(russian, _)| *russian == name)
+        .map(|(_, english)| -> &'n str { english })
+}
+
+/// Lowercases the text, replaces 'ё' with 'е' and collapses whitespace
+fn fold_case(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+        .replace('ё', "е")
+}
+
+/// Transliterates Cyrillic letters of lowercase text to Latin, leaving other characters as is.
+///
+/// Uses the common romanization found in region names: "москва" becomes "moskva",
+/// "нижний новгород" becomes "nizhniy novgorod".
+pub fn transliterate(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for letter in text.chars() {
+        let latin = match letter {
+            'а' => "a",  'б' => "b",  'в' => "v",  'г' => "g",   'д' => "d",
+            'е' => "e",  'ё' => "e",  'ж' => "zh", 'з' => "z",   'и' => "i",
+            'й' => "y",  'к' => "k",  'л' => "l",  'м' => "m",   'н' => "n",
+            'о' => "o",  'п' => "p",  'р' => "r",  'с' => "s",   'т' => "t",
+            'у' => "u",  'ф' => "f",  'х' => "kh", 'ц' => "ts",  'ч' => "ch",
+            'ш' => "sh", 'щ' => "shch", 'ъ' => "", 'ы' => "y",   'ь' => "",
+            'э' => "e",  'ю' => "yu", 'я' => "ya",
+            _ => { result.push(letter); continue; }
+        };
+        result.push_str(latin);
+    }
+    result
+}
+
+/// Levenshtein distance between two strings, counted in characters
+fn edit_distance(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut previous: Vec<usize> = (0..=second.len()).collect();
+    for (i, first_char) in first.chars().enumerate() {
+        let mut current = vec![i + 1; second.len() + 1];
+        for (j, second_char) in second.iter().enumerate() {
+            let substitution = previous[j] + usize::from(first_char != *second_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[second.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions() -> Vec<Region> {
+        vec![
+            Region { name: "Москва".to_string(), id: 213, parent_id: Some(1) },
+            Region { name: "Москва и Московская область".to_string(), id: 1, parent_id: Some(3) },
+            Region { name: "Санкт-Петербург".to_string(), id: 2, parent_id: Some(10174) },
+            Region { name: "Орёл".to_string(), id: 10, parent_id: Some(4) },
+            Region { name: "Europe".to_string(), id: 111, parent_id: Some(0) },
+        ]
+    }
+
+    fn resolve(resolver: &RegionResolver, query: &str) -> Vec<(i64, MatchKind)> {
+        resolver.resolve(query).iter().map(|found| (found.region.id, found.kind)).collect()
+    }
+
+    #[test]
+    fn match_kinds() {
+        let regions = regions();
+        let resolver = RegionResolver::new(&regions);
+
+
+        assert_eq!(resolve(&resolver, "Москва"), vec![(213, MatchKind::Exact), (1, MatchKind::Prefix)]);
+        assert_eq!(resolve(&resolver, "МОСКВА")[0], (213, MatchKind::CaseInsensitive));
+        assert_eq!(resolve(&resolver, "Орел"), vec![(10, MatchKind::CaseInsensitive)]);
+        assert_eq!(resolve(&resolver, "Moskva")[0], (213, MatchKind::Transliterated));
+        assert_eq!(resolve(&resolver, "sankt"), vec![(2, MatchKind::Prefix)]);
+        assert_eq!(resolve(&resolver, "Sankt-Peterburh"), vec![(2, MatchKind::Fuzzy)]);
+        assert_eq!(resolve(&resolver, "europa"), vec![(111, MatchKind::Fuzzy)]);
+        assert!(resolve(&resolver, "Vladivostok").is_empty())
+    }
+
+    #[test]
+    fn aliases() {
+        let regions = regions();
+        let resolver = RegionResolver::new(&regions)
+            .with_alias("Moscow", 213)
+            .with_alias("St. Petersburg", 2);
+
+
+        assert_eq!(resolver.best("Moscow").unwrap().id, 213);
+        assert_eq!(resolver.best("st. petersburg").unwrap().id, 2)
+    }
+
+    #[test]
+    fn builtin_aliases() {
+        let regions = regions();
+        let resolver = RegionResolver::new(&regions);
+
+
+        assert_eq!(resolver.resolve("Moscow")[0].region.id, 213);
+        assert_eq!(resolver.best("Moscow").unwrap().id, 213);
+        assert_eq!(resolver.best("saint petersburg").unwrap().id, 2)
+    }
+
+    #[test]
+    fn disabled_fuzzy_matching() {
+        let regions = regions();
+        let resolver = RegionResolver::new(&regions).with_max_distance(0);
+
+
+        assert!(resolver.resolve("Sankt-Peterburh").is_empty())
+    }
+
+    #[test]
+    fn transliteration() {
+        assert_eq!(transliterate("нижний новгород"), "nizhniy novgorod");
+        assert_eq!(transliterate("хабаровск"), "khabarovsk");
+        assert_eq!(transliterate("rust"), "rust")
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("москва", "москва"), 0)
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::WordstatError;
+use crate::region::Region;
+
+/// The region hierarchy built from the flat list returned by [get_regions](crate::region::get_regions).
+///
+/// Regions are indexed by ID and by name. Queries for unknown IDs return
+/// nothing instead of failing.
+/// ```rust,ignore
+/// let tree = RegionTree::new(get_regions(&client).await?)?;
+/// let moscow = tree.find_by_name("Москва")[0];
+/// let path: Vec<&str> = tree.path_to_root(moscow.id).iter().map(|region| region.name.as_str()).collect();
+/// ```
+#[derive(Debug)]
+pub struct RegionTree {
+    regions: Vec<Region>,
+    by_id: HashMap<i64, usize>,
+    by_name: HashMap<String, Vec<usize>>,
+    children: HashMap<i64, Vec<usize>>
+}
+
+impl RegionTree {
+    /// Builds the tree. Returns an error if a region ID is repeated, a region
+    /// refers to a parent that is not in the list or the parents form a cycle.
+    pub fn new(regions: Vec<Region>) -> Result<Self, WordstatError> {
+        let mut by_id = HashMap::new();
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut children: HashMap<i64, Vec<usize>> = HashMap::new();
+
+        for (index, region) in regions.iter().enumerate() {
+            if by_id.insert(region.id, index).is_some() {
+                return Err(WordstatError::DuplicateRegion { id: region.id });
+            }
+            by_name.entry(region.name.clone()).or_default().push(index);
+        }
+        for (index, region) in regions.iter().enumerate() {
+            let Some(parent_id) = region.parent_id else { continue };
+            if !by_id.contains_key(&parent_id) {
+                return Err(WordstatError::OrphanedRegion { id: region.id, parent_id });
+            }
+            children.entry(parent_id).or_default().push(index);
+        }
+
+        let tree = RegionTree { regions, by_id, by_name, children };
+        tree.check_cycles()?;
+        Ok(tree)
+    }
+
+    fn check_cycles(&self) -> Result<(), WordstatError> {
+        let mut acyclic: HashSet<i64> = HashSet::new();
+        for region in &self.regions {
+            let mut path: HashSet<i64> = HashSet::new();
+            let mut current = Some(region);
+            while let Some(node) = current {
+                if acyclic.contains(&node.id) { break; }
+                if !path.insert(node.id) {
+                    return Err(WordstatError::RegionCycle { id: node.id });
+                }
+                current = self.parent(node.id);
+            }
+            acyclic.extend(path);
+        }
+        Ok(())
+    }
+
+    /// Returns all regions in the order they were passed
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the region with the passed ID
+    pub fn get(&self, id: i64) -> Option<&Region> {
+        self.by_id.get(&id).map(|index| &self.regions[*index])
+    }
+
+    /// Returns the regions with exactly the passed name
+    pub fn find_by_name(&self, name: &str) -> Vec<&Region> {
+        self.by_name.get(name)
+            .map(|indices| indices.iter().map(|index| &self.regions[*index]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the regions without a parent
+    pub fn roots(&self) -> Vec<&Region> {
+        self.regions.iter().filter(|region| region.parent_id.is_none()).collect()
+    }
+
+    /// Returns the parent of the region
+    pub fn parent(&self, id: i64) -> Option<&Region> {
+        self.get(id)?.parent_id.and_then(|parent_id| self.get(parent_id))
+    }
+
+    /// Returns the direct children of the region
+    pub fn children(&self, id: i64) -> Vec<&Region> {
+        self.children.get(&id)
+            .map(|indices| indices.iter().map(|index| &self.regions[*index]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the parent of the region, its parent and so on up to the root
+    pub fn ancestors(&self, id: i64) -> Vec<&Region> {
+        let mut ancestors = vec![];
+        let mut current = self.parent(id);
+        while let Some(region) = current {
+            ancestors.push(region);
+            current = self.parent(region.id);
+        }
+        ancestors
+    }
+
+    /// Returns all regions below the region, closest ones first
+    pub fn descendants(&self, id: i64) -> Vec<&Region> {
+        let mut descendants = vec![];
+        let mut queue: VecDeque<i64> = VecDeque::from([id]);
+        while let Some(current) = queue.pop_front() {
+            for child in self.children(current) {
+                queue.push_back(child.id);
+                descendants.push(child);
+            }
+        }
+        descendants
+    }
+
+    /// Returns the region followed by its [ancestors](RegionTree::ancestors).
+    /// Empty if the region is unknown.
+    pub fn path_to_root(&self, id: i64) -> Vec<&Region> {
+        let Some(region) = self.get(id) else { return vec![] };
+        let mut path = vec![region];
+        path.extend(self.ancestors(id));
+        path
+    }
+
+    /// Returns the amount of ancestors of the region, 0 for roots
+    pub fn depth(&self, id: i64) -> Option<usize> {
+        self.get(id)?;
+        Some(self.ancestors(id).len())
+    }
+
+    /// Returns true if the first region is an ancestor of the second one
+    pub fn is_ancestor(&self, ancestor_id: i64, id: i64) -> bool {
+        self.ancestors(id).iter().any(|region| region.id == ancestor_id)
+    }
+
+    /// Returns the deepest region that both regions belong to. A region counts
+    /// as belonging to itself.
+    pub fn lowest_common_ancestor(&self, first_id: i64, second_id: i64) -> Option<&Region> {
+        let first_path: HashSet<i64> = self.path_to_root(first_id).iter().map(|region| region.id).collect();
+        self.path_to_root(second_id).into_iter().find(|region| first_path.contains(&region.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(name: &str, id: i64, parent_id: Option<i64>) -> Region {
+        Region { name: name.to_string(), id, parent_id }
+    }
+
+    fn tree() -> RegionTree {
+        RegionTree::new(vec![
+            region("All", 0, None),
+            region("Europe", 111, Some(0)),
+            region("Russia", 225, Some(0)),
+            region("Central Federal District", 3, Some(225)),
+            region("Moscow and Moscow Oblast", 1, Some(3)),
+            region("Moscow", 213, Some(1)),
+            region("Northwestern Federal District", 17, Some(225)),
+            region("Saint Petersburg", 2, Some(17)),
+        ]).unwrap()
+    }
+
+    fn ids(regions: Vec<&Region>) -> Vec<i64> {
+        regions.iter().map(|region| region.id).collect()
+    }
+
+    #[test]
+    fn navigate() {
+        let tree = tree();
+
+
+        assert_eq!(ids(tree.children(225)), vec![3, 17]);
+        assert_eq!(ids(tree.ancestors(213)), vec![1, 3, 225, 0]);
+        assert_eq!(ids(tree.path_to_root(213)), vec![213, 1, 3, 225, 0]);
+        assert_eq!(ids(tree.descendants(225)), vec![3, 17, 1, 2, 213]);
+        assert_eq!(ids(tree.roots()), vec![0]);
+        assert_eq!(tree.depth(213), Some(4));
+        assert_eq!(tree.depth(0), Some(0));
+        assert_eq!(tree.depth(5), None);
+        assert!(tree.is_ancestor(225, 2));
+        assert!(!tree.is_ancestor(111, 2));
+        assert_eq!(tree.find_by_name("Moscow")[0].id, 213)
+    }
+
+    #[test]
+    fn lowest_common_ancestor() {
+        let tree = tree();
+
+
+        assert_eq!(tree.lowest_common_ancestor(213, 2).map(|region| region.id), Some(225));
+        assert_eq!(tree.lowest_common_ancestor(213, 111).map(|region| region.id), Some(0));
+        assert_eq!(tree.lowest_common_ancestor(213, 3).map(|region| region.id), Some(3));
+        assert!(tree.lowest_common_ancestor(213, 5).is_none())
+    }
+
+    #[test]
+    fn orphaned_region() {
+        let received = RegionTree::new(vec![
+            region("All", 0, None),
+            region("Moscow", 213, Some(1)),
+        ]);
+
+
+        assert!(matches!(received, Err(WordstatError::OrphanedRegion { id: 213, parent_id: 1 })))
+    }
+
+    #[test]
+    fn cycle() {
+        let received = RegionTree::new(vec![
+            region("All", 0, None),
+            region("A", 1, Some(3)),
+            region("B", 2, Some(1)),
+            region("C", 3, Some(2)),
+        ]);
+
+
+        assert!(matches!(received, Err(WordstatError::RegionCycle { .. })))
+    }
+
+    #[test]
+    fn duplicate_region() {
+        let received = RegionTree::new(vec![
+            region("All", 0, None),
+            region("All", 0, None),
+        ]);
+
+
+        assert!(matches!(received, Err(WordstatError::DuplicateRegion { id: 0 })))
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::WordstatError;
+use crate::client::Client;
+use crate::create_report::{ReportRequest, create_report};
+use crate::delete_report::delete_report;
+use crate::file::write_atomically;
+use crate::get_report::{ReportEntry, ReportEntryModel};
+use crate::normalize::normalize_phrase;
+use crate::wait_for_report::{PollOptions, wait_for_report};
+
+/// Identifies the results of a [ReportRequest] regardless of the phrase order and the order
+/// of regions. Phrases are compared in the [normalized](crate::normalize::normalize_phrase) form,
+/// the same way [with_dedupe](ReportRequest::with_dedupe) compares them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    phrases: Vec<String>,
+    geo_id: Vec<i64>
+}
+
+impl CacheKey {
+    /// Creates the key of the request
+    pub fn new(request: &ReportRequest) -> Self {
+        let mut phrases: Vec<String> = request.phrases().iter()
+            .map(|phrase| normalize(phrase))
+            .collect();
+        phrases.sort();
+        phrases.dedup();
+        let mut geo_id = request.geo_id().to_vec();
+        geo_id.sort();
+        geo_id.dedup();
+        CacheKey { phrases, geo_id }
+    }
+
+    /// Returns the normalized phrases in alphabetical order
+    pub fn phrases(&self) -> &[String] {
+        &self.phrases
+    }
+
+    /// Returns the region IDs in ascending order
+    pub fn geo_id(&self) -> &[i64] {
+        &self.geo_id
+    }
+}
+
+/// Brings the phrase to the normalized form. Phrases that can't be parsed are
+/// only lowercased with collapsed whitespace.
+fn normalize(phrase: &str) -> String {
+    normalize_phrase(phrase)
+        .unwrap_or_else(|_| phrase.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase())
+}
+
+/// A report stored in a [ReportCache]
+#[derive(Debug, Clone)]
+pub struct CachedReport {
+    /// The report entries
+    pub entries: Vec<ReportEntry>,
+    /// The time the report was downloaded
+    pub fetched_at: SystemTime
+}
+
+impl CachedReport {
+    /// Returns true if the report was downloaded less than `max_age` ago
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        SystemTime::now().duration_since(self.fetched_at)
+            .map(|age| age < max_age)
+            .unwrap_or(true)
+    }
+}
+
+/// Storage for downloaded reports, used by [CachedReports].
+///
+/// Implement it to keep the reports somewhere other than the memory or the file system.
+#[async_trait]
+pub trait ReportCache: Send + Sync {
+    /// Returns the report stored with the key, if there is one
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedReport>, WordstatError>;
+    /// Stores the report, replacing the previous one with the same key
+    async fn put(&self, key: &CacheKey, report: CachedReport) -> Result<(), WordstatError>;
+}
+
+/// Keeps the reports in memory for the lifetime of the program
+#[derive(Debug, Default)]
+pub struct MemoryReportCache {
+    reports: Mutex<HashMap<CacheKey, CachedReport>>
+}
+
+impl MemoryReportCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        MemoryReportCache::default()
+    }
+}
+
+#[async_trait]
+impl ReportCache for MemoryReportCache {
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedReport>, WordstatError> {
+        Ok(self.reports.lock().unwrap().get(key).cloned())
+    }
+
+    async fn put(&self, key: &CacheKey, report: CachedReport) -> Result<(), WordstatError> {
+        self.reports.lock().unwrap().insert(key.clone(), report);
+        Ok(())
+    }
+}
+
+/// Keeps every report in a separate JSON file inside a directory.
+///
+/// Files that can not be read are treated as missing. The files are read and written
+/// with [tokio::fs], so the cache needs a tokio runtime.
+#[derive(Debug)]
+pub struct FileReportCache {
+    directory: PathBuf
+}
+
+/// The contents of a report file
+#[derive(Serialize, Deserialize)]
+struct ReportFile {
+    phrases: Vec<String>,
+    geo_id: Vec<i64>,
+    /// Seconds since the Unix epoch
+    fetched_at: u64,
+    entries: Vec<ReportEntryModel>
+}
+
+impl FileReportCache {
+    /// Creates a cache in the passed directory. The directory is created on the first write.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        FileReportCache { directory: directory.into() }
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        // FNV-1a, as the file names have to stay the same between program versions
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let text = format!("{}\n{:?}", key.phrases.join("\n"), key.geo_id);
+        for byte in text.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        self.directory.join(format!("{hash:016x}.json"))
+    }
+}
+
+#[async_trait]
+impl ReportCache for FileReportCache {
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedReport>, WordstatError> {
+        let Ok(contents) = tokio::fs::read(self.path(key)).await else { return Ok(None) };
+        let Ok(file) = serde_json::from_slice::<ReportFile>(&contents) else { return Ok(None) };
+        // Different keys can share a file name, the key stored in the file tells them apart
+        if file.phrases != key.phrases || file.geo_id != key.geo_id { return Ok(None); }
+
+        Ok(Some(CachedReport {
+            entries: file.entries.into_iter().map(ReportEntry::from).collect(),
+            fetched_at: UNIX_EPOCH + Duration::from_secs(file.fetched_at)
+        }))
+    }
+
+    async fn put(&self, key: &CacheKey, report: CachedReport) -> Result<(), WordstatError> {
+        let file = ReportFile {
+            phrases: key.phrases.clone(),
+            geo_id: key.geo_id.clone(),
+            fetched_at: report.fetched_at.duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default(),
+            entries: report.entries.iter().map(ReportEntryModel::from).collect()
+        };
+        let contents = serde_json::to_vec(&file).expect("Reports are always serializable");
+
+        tokio::fs::create_dir_all(&self.directory).await?;
+        write_atomically(&self.path(key), contents).await?;
+        Ok(())
+    }
+}
+
+/// Serves reports from a [ReportCache] while they are fresh and generates them
+/// through the API otherwise, so that repeated requests do not burn the report quota.
+/// ```rust,ignore
+/// let reports = CachedReports::new(&client, FileReportCache::new("reports"))
+///     .with_max_age(Duration::from_secs(12 * 60 * 60));
+/// let entries = reports.get_report(&request).await?;
+/// ```
+pub struct CachedReports<'a, C: ReportCache> {
+    client: &'a Client,
+    cache: C,
+    max_age: Duration,
+    poll_options: PollOptions,
+    store_error: Mutex<Option<WordstatError>>
+}
+
+impl<'a, C: ReportCache> CachedReports<'a, C> {
+    /// Creates the caching layer treating reports as fresh for a day
+    pub fn new(client: &'a Client, cache: C) -> Self {
+        CachedReports {
+            client,
+            cache,
+            max_age: Duration::from_secs(24 * 60 * 60),
+            poll_options: PollOptions::new(),
+            store_error: Mutex::new(None)
+        }
+    }
+
+    /// Sets how long a downloaded report is served from the cache
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Sets how the report status is polled while waiting for new reports
+    pub fn with_poll_options(mut self, poll_options: PollOptions) -> Self {
+        self.poll_options = poll_options;
+        self
+    }
+
+    /// Returns the underlying cache
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    /// Returns the error of the last failed attempt to store a downloaded report,
+    /// clearing it. Failing to store a report doesn't fail [get_report](CachedReports::get_report).
+    pub fn take_store_error(&self) -> Option<WordstatError> {
+        self.store_error.lock().unwrap().take()
+    }
+
+    /// Returns the cached report for the request if it is fresh. Otherwise creates
+    /// the report, waits for it with [wait_for_report], deletes it from the server
+    /// and stores it in the cache.
+    ///
+    /// The entries are returned in the order of the request phrases and carry
+    /// the phrases as they are written in the request, whether they come from the cache or not.
+    /// The downloaded entries are returned even if they can not be stored, see
+    /// [take_store_error](CachedReports::take_store_error).
+    pub async fn get_report(&self, request: &ReportRequest) -> Result<Vec<ReportEntry>, WordstatError> {
+        let key = CacheKey::new(request);
+        if let Some(report) = self.cache.get(&key).await? {
+            if report.is_fresh(self.max_age) { return Ok(map_to_request(request, &report.entries)); }
+        }
+
+        let report_id = create_report(self.client, request).await?;
+        let entries = match wait_for_report(self.client, report_id, self.poll_options.clone()).await {
+            Ok(entries) => { entries }
+            Err(error) => {
+                let _ = delete_report(self.client, report_id).await;
+                return Err(error);
+            }
+        };
+        // The entries are already downloaded, a report left on the server only takes a slot
+        let _ = delete_report(self.client, report_id).await;
+
+        let entries = map_to_request(request, &entries);
+        if let Err(error) = self.cache.put(&key, CachedReport { entries: entries.clone(), fetched_at: SystemTime::now() }).await {
+            *self.store_error.lock().unwrap() = Some(error);
+        }
+        Ok(entries)
+    }
+}
+
+/// Picks the entry of every request phrase from the entries, which can be in another order
+/// or come from another request with the same key. Phrases without an entry are skipped.
+fn map_to_request(request: &ReportRequest, entries: &[ReportEntry]) -> Vec<ReportEntry> {
+    request.phrases().iter()
+        .filter_map(|phrase| {
+            let normalized = normalize(phrase);
+            let entry = entries.iter().find(|entry| normalize(&entry.phrase) == normalized)?;
+            Some(ReportEntry { phrase: phrase.clone(), ..entry.clone() })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+
+    const REPORT: &str = r#"{"data": [{"Phrase": "rust", "GeoID": [213], "SearchedWith": [{"Phrase": "rust", "Shows": 5}]}]}"#;
+
+    /// Returns a client that generates a report once
+    fn client(times: usize) -> Client {
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .times(times * 4)
+            .returning(|_url, payload| {
+                let response = match payload["method"].as_str().unwrap() {
+                    "CreateNewWordstatReport" => { r#"{"data": 5}"# }
+                    "GetWordstatReportList" => { r#"{"data": [{"ReportID": 5, "StatusReport": "Done"}]}"# }
+                    "GetWordstatReport" => { REPORT }
+                    "DeleteWordstatReport" => { r#"{"data": 1}"# }
+                    method => { panic!("Unexpected method {method}") }
+                };
+                Ok(serde_json::from_str::<Value>(response).unwrap())
+            });
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_clock(ManualClock::new());
+        client
+    }
+
+    fn request(phrases: &Vec<&str>, geo_id: &[i64]) -> ReportRequest {
+        ReportRequest::new().with_phrases(phrases).unwrap().with_geo(geo_id)
+    }
+
+    #[test]
+    fn normalized_key() {
+        let first = CacheKey::new(&request(&vec!["Rust  lang", "купить ёлку"], &[213, -1, 213]));
+
+
+        let second = CacheKey::new(&request(&vec!["елку купить", " lang rust"], &[-1, 213]));
+
+
+        assert_eq!(first, second);
+        assert_eq!(first.phrases(), ["lang rust", "елку купить"]);
+        assert_eq!(first.geo_id(), [-1, 213])
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_reports() {
+        let client = client(1);
+        let reports = CachedReports::new(&client, MemoryReportCache::new());
+
+
+        reports.get_report(&request(&vec!["rust"], &[213])).await.unwrap();
+        let received = reports.get_report(&request(&vec!["Rust"], &[213])).await.unwrap();
+
+
+        assert_eq!(received[0].searched_with[0].shows, 5)
+    }
+
+    #[tokio::test]
+    async fn refreshes_stale_reports() {
+        let client = client(1);
+        let cache = MemoryReportCache::new();
+        let request = request(&vec!["rust"], &[213]);
+        let stale = CachedReport { entries: vec![], fetched_at: SystemTime::now() - Duration::from_secs(120) };
+        cache.put(&CacheKey::new(&request), stale).await.unwrap();
+        let reports = CachedReports::new(&client, cache).with_max_age(Duration::from_secs(60));
+
+
+        let received = reports.get_report(&request).await.unwrap();
+
+
+        assert_eq!(received.len(), 1)
+    }
+
+    #[tokio::test]
+    async fn file_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = CacheKey::new(&request(&vec!["rust"], &[213]));
+        let entries: Vec<ReportEntry> = crate::response::parse_response::<Vec<ReportEntryModel>>(REPORT.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(ReportEntry::from)
+            .collect();
+        FileReportCache::new(dir.path().join("reports"))
+            .put(&key, CachedReport { entries: entries.clone(), fetched_at: UNIX_EPOCH + Duration::from_secs(100) })
+            .await
+            .unwrap();
+
+
+        let received = FileReportCache::new(dir.path().join("reports")).get(&key).await.unwrap().unwrap();
+
+
+        assert_eq!(received.entries, entries);
+        assert_eq!(received.fetched_at, UNIX_EPOCH + Duration::from_secs(100));
+        let other = CacheKey::new(&request(&vec!["rust"], &[]));
+        assert!(FileReportCache::new(dir.path().join("reports")).get(&other).await.unwrap().is_none())
+    }
+
+    #[tokio::test]
+    async fn maps_cached_entries_to_request() {
+        let client = client(0);
+        let cache = MemoryReportCache::new();
+        let entry = |phrase: &str, shows| ReportEntry {
+            phrase: phrase.to_string(),
+            geo_id: vec![213],
+            searched_with: vec![crate::get_report::WordstatItem { phrase: phrase.to_string(), shows }],
+            searched_also: vec![]
+        };
+        let filled_by = request(&vec!["rust", "Cargo"], &[213]);
+        cache.put(&CacheKey::new(&filled_by), CachedReport { entries: vec![entry("rust", 5), entry("Cargo", 7)], fetched_at: SystemTime::now() }).await.unwrap();
+        let reports = CachedReports::new(&client, cache);
+
+
+        let received = reports.get_report(&request(&vec!["cargo", "Rust", "cargo"], &[213])).await.unwrap();
+
+
+        let received: Vec<(&str, i64)> = received.iter().map(|entry| (entry.phrase.as_str(), entry.searched_with[0].shows)).collect();
+        assert_eq!(received, vec![("cargo", 7), ("Rust", 5), ("cargo", 7)])
+    }
+
+    /// Returns a client whose report fails or whose delete request fails
+    fn failing_client(report_status: &'static str, delete_response: &'static str) -> Client {
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .withf(|_url, payload| payload["method"] == "DeleteWordstatReport")
+            .times(1)
+            .returning(move |_url, _payload| Ok(serde_json::from_str::<Value>(delete_response).unwrap()));
+        transport.expect_post()
+            .returning(move |_url, payload| {
+                let response = match payload["method"].as_str().unwrap() {
+                    "CreateNewWordstatReport" => { r#"{"data": 5}"#.to_string() }
+                    "GetWordstatReportList" => { format!(r#"{{"data": [{{"ReportID": 5, "StatusReport": "{report_status}"}}]}}"#) }
+                    "GetWordstatReport" => { REPORT.to_string() }
+                    method => { panic!("Unexpected method {method}") }
+                };
+                Ok(serde_json::from_str::<Value>(&response).unwrap())
+            });
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_clock(ManualClock::new());
+        client
+    }
+
+    #[tokio::test]
+    async fn deletes_failed_report() {
+        let client = failing_client("Failed", r#"{"data": 1}"#);
+        let reports = CachedReports::new(&client, MemoryReportCache::new());
+
+
+        let received = reports.get_report(&request(&vec!["rust"], &[213])).await;
+
+
+        assert!(matches!(received, Err(WordstatError::ReportFailed { report_id: 5 })))
+    }
+
+    #[tokio::test]
+    async fn keeps_entries_when_delete_fails() {
+        let client = failing_client("Done", r#"{"error_code": 500}"#);
+        let request = request(&vec!["rust"], &[213]);
+        let reports = CachedReports::new(&client, MemoryReportCache::new());
+
+
+        let received = reports.get_report(&request).await.unwrap();
+
+
+        assert_eq!(received[0].phrase, "rust");
+        assert!(reports.cache().get(&CacheKey::new(&request)).await.unwrap().is_some())
+    }
+
+    #[tokio::test]
+    async fn same_entries_on_hit_and_miss() {
+        let client = client(1);
+        let reports = CachedReports::new(&client, MemoryReportCache::new());
+        let request = request(&vec!["Rust"], &[213]);
+
+
+        let miss = reports.get_report(&request).await.unwrap();
+        let hit = reports.get_report(&request).await.unwrap();
+
+
+        assert_eq!(miss, hit);
+        assert_eq!(miss[0].phrase, "Rust")
+    }
+
+    /// A cache that can't store anything
+    struct ReadOnlyCache;
+
+    #[async_trait]
+    impl ReportCache for ReadOnlyCache {
+        async fn get(&self, _key: &CacheKey) -> Result<Option<CachedReport>, WordstatError> {
+            Ok(None)
+        }
+
+        async fn put(&self, _key: &CacheKey, _report: CachedReport) -> Result<(), WordstatError> {
+            Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_entries_when_store_fails() {
+        let client = client(1);
+        let reports = CachedReports::new(&client, ReadOnlyCache);
+
+
+        let received = reports.get_report(&request(&vec!["rust"], &[213])).await.unwrap();
+
+
+        assert_eq!(received[0].searched_with[0].shows, 5);
+        assert!(matches!(reports.take_store_error(), Some(WordstatError::Io { .. })));
+        assert!(reports.take_store_error().is_none())
+    }
+}
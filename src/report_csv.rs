@@ -0,0 +1,216 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use crate::WordstatError;
+use crate::get_report::{ReportEntry, WordstatItem};
+
+/// The UTF-8 byte order mark, which makes Excel read the file as UTF-8
+/// instead of the system code page, so that Cyrillic phrases stay readable
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+const HEADER: [&str; 5] = ["phrase", "geo_id", "relation", "item_phrase", "shows"];
+
+/// Describes the CSV layout used by [write_csv] and [read_csv].
+///
+/// Every row holds one keyword of a report entry: the source phrase, the region IDs
+/// separated by spaces, the relation (`searched_with` or `searched_also`), the keyword and
+/// its shows. Entries without keywords are written as a single row with empty last columns.
+/// ```
+/// # use wordstat_rs::*;
+/// // Semicolons and the byte order mark for Excel with a Russian locale
+/// let options = CsvOptions::new()
+///     .with_delimiter(b';')
+///     .with_bom(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    delimiter: u8,
+    header: bool,
+    bom: bool
+}
+
+impl CsvOptions {
+    /// Creates comma separated options with a header and without the byte order mark
+    pub fn new() -> Self {
+        CsvOptions { delimiter: b',', header: true, bom: false }
+    }
+
+    /// Sets the field delimiter
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets whether the first row holds the column names
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets whether the file starts with the UTF-8 byte order mark. The reader
+    /// skips the mark whether this is set or not.
+    pub fn with_bom(mut self, bom: bool) -> Self {
+        self.bom = bom;
+        self
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions::new()
+    }
+}
+
+/// Writes the report entries as CSV rows
+pub fn write_csv<W: Write>(mut writer: W, entries: &[ReportEntry], options: &CsvOptions) -> Result<(), WordstatError> {
+    if options.bom { writer.write_all(BOM)?; }
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .from_writer(writer);
+
+    if options.header { writer.write_record(HEADER).map_err(csv_error)?; }
+    for entry in entries {
+        let geo_id = entry.geo_id.iter().map(i64::to_string).collect::<Vec<String>>().join(" ");
+        let items = entry.searched_with.iter().map(|item| ("searched_with", item))
+            .chain(entry.searched_also.iter().map(|item| ("searched_also", item)));
+        let mut empty = true;
+        for (relation, item) in items {
+            writer.write_record([entry.phrase.as_str(), &geo_id, relation, &item.phrase, &item.shows.to_string()])
+                .map_err(csv_error)?;
+            empty = false;
+        }
+        if empty {
+            writer.write_record([entry.phrase.as_str(), &geo_id, "", "", ""]).map_err(csv_error)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the report entries written by [write_csv].
+///
+/// Consecutive rows with the same phrase and regions are joined into one entry.
+pub fn read_csv<R: Read>(reader: R, options: &CsvOptions) -> Result<Vec<ReportEntry>, WordstatError> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(BOM) { reader.consume(BOM.len()); }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .has_headers(options.header)
+        .from_reader(reader);
+
+    let mut entries: Vec<ReportEntry> = vec![];
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        let line = record.position().map(|position| position.line()).unwrap_or_default();
+        if record.len() != HEADER.len() {
+            return Err(bad_csv(line, format!("expected {} fields, found {}", HEADER.len(), record.len())));
+        }
+
+        let geo_id = record[1].split_whitespace()
+            .map(|id| id.parse().map_err(|_| bad_csv(line, format!("invalid region ID '{id}'"))))
+            .collect::<Result<Vec<i64>, WordstatError>>()?;
+        let entry = match entries.last_mut() {
+            Some(entry) if entry.phrase == record[0] && entry.geo_id == geo_id => { entry }
+            _ => {
+                entries.push(ReportEntry {
+                    phrase: record[0].to_string(),
+                    geo_id,
+                    searched_with: vec![],
+                    searched_also: vec![]
+                });
+                entries.last_mut().unwrap()
+            }
+        };
+
+        let relation = &record[2];
+        if relation.is_empty() { continue; }
+        let shows = record[4].parse()
+            .map_err(|_| bad_csv(line, format!("invalid shows '{}'", &record[4])))?;
+        let item = WordstatItem { phrase: record[3].to_string(), shows };
+        match relation {
+            "searched_with" => { entry.searched_with.push(item) }
+            "searched_also" => { entry.searched_also.push(item) }
+            _ => { return Err(bad_csv(line, format!("unknown relation '{relation}'"))); }
+        }
+    }
+
+    Ok(entries)
+}
+
+fn csv_error(error: csv::Error) -> WordstatError {
+    let line = error.position().map(|position| position.line()).unwrap_or_default();
+    if !error.is_io_error() { return bad_csv(line, error.to_string()); }
+    match error.into_kind() {
+        csv::ErrorKind::Io(error) => { WordstatError::Io { source: error } }
+        _ => { unreachable!("Checked to be an I/O error") }
+    }
+}
+
+fn bad_csv(line: u64, reason: String) -> WordstatError {
+    WordstatError::BadCsv { line, reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(phrase: &str, shows: i64) -> WordstatItem {
+        WordstatItem { phrase: phrase.to_string(), shows }
+    }
+
+    fn entries() -> Vec<ReportEntry> {
+        vec![
+            ReportEntry {
+                phrase: "купить велосипед".to_string(),
+                geo_id: vec![213, -1],
+                searched_with: vec![item("купить велосипед", 5000), item("купить велосипед, недорого", 120)],
+                searched_also: vec![item("велосипед \"stels\"", 40)]
+            },
+            ReportEntry {
+                phrase: "rust".to_string(),
+                geo_id: vec![],
+                searched_with: vec![],
+                searched_also: vec![]
+            },
+        ]
+    }
+
+    #[test]
+    fn write_rows() {
+        let mut output = vec![];
+
+
+        write_csv(&mut output, &entries()[..1], &CsvOptions::new().with_delimiter(b';').with_bom(true)).unwrap();
+
+
+        let expected = "\u{feff}phrase;geo_id;relation;item_phrase;shows\n\
+            купить велосипед;213 -1;searched_with;купить велосипед;5000\n\
+            купить велосипед;213 -1;searched_with;купить велосипед, недорого;120\n\
+            купить велосипед;213 -1;searched_also;\"велосипед \"\"stels\"\"\";40\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected)
+    }
+
+    #[test]
+    fn round_trip() {
+        for options in [CsvOptions::new(), CsvOptions::new().with_delimiter(b'\t').with_header(false).with_bom(true)] {
+            let mut output = vec![];
+            write_csv(&mut output, &entries(), &options).unwrap();
+
+
+            let received = read_csv(output.as_slice(), &options).unwrap();
+
+
+            assert_eq!(received, entries())
+        }
+    }
+
+    #[test]
+    fn invalid_shows() {
+        let input = "phrase,geo_id,relation,item_phrase,shows\nrust,,searched_with,rust,many\n";
+
+
+        let received = read_csv(input.as_bytes(), &CsvOptions::new());
+
+
+        assert!(matches!(received, Err(WordstatError::BadCsv { line: 2, .. })))
+    }
+}
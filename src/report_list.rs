@@ -1,12 +1,11 @@
-use serde_json::Value;
-use crate::{WordstatError, check_status};
-use mockall_double::double;
-#[double] // For mocking the client in unit tests
+use serde::{Deserialize, Serialize};
+use crate::WordstatError;
 use crate::client::Client;
 
 
 /// Possible states of the report
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StatusCode {
     Done,
     Pending,
@@ -17,6 +16,7 @@ pub enum StatusCode {
 /// Struct describing the status of the report
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReportStatus {
     /// The id of the report
     pub report_id: i64,
@@ -24,51 +24,62 @@ pub struct ReportStatus {
     pub status: StatusCode
 }
 
+pub(crate) const METHOD: &str = "GetWordstatReportList";
+
 /// Sends a request to the API asking for a list of reports
 pub async fn get_report_list(client: &Client) -> Result<Vec<ReportStatus>, WordstatError> {
-    let method = "GetWordstatReportList";
-    let result = client.post(method, None).await?;
-
-    check_status(&result)?;
+    let reports: Vec<ReportStatusModel> = client.call(METHOD, None).await?;
 
-    let Some(data) = result.get("data") else { return Err(WordstatError::BadResponse{ reason: "No data field in response" }) };
-    let Value::Array(reports) = data else { return Err(WordstatError::BadResponse{ reason: "Data field is not an array" }) };
+    Ok(reports.into_iter().map(ReportStatus::from).collect())
+}
 
-    parse_reports(&reports)
+/// A report status as it is returned by the API
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ReportStatusModel {
+    #[serde(rename = "ReportID")]
+    report_id: i64,
+    #[serde(rename = "StatusReport")]
+    status: String
 }
 
-fn parse_reports(data: &Vec<Value>) -> Result<Vec<ReportStatus>, WordstatError> {
-    let mut reports: Vec<ReportStatus> = vec![];
+impl From<ReportStatusModel> for ReportStatus {
+    fn from(model: ReportStatusModel) -> Self {
+        let status = match model.status.as_str() {
+            "Done"      => { StatusCode::Done }
+            "Pending"   => { StatusCode::Pending }
+            "Failed"    => { StatusCode::Failed }
+            _           => { StatusCode::Unknown }
+        };
 
-    for report in data {
-        reports.push(parse_report(report)?);
+        ReportStatus {
+            report_id: model.report_id,
+            status
+        }
     }
-
-    Ok(reports)
 }
 
-fn parse_report(report: &Value) -> Result<ReportStatus, WordstatError> {
-    let Some(id_val) = report.get("ReportID") else { return Err(WordstatError::BadResponse{ reason: "No ReportID field" }) };
-    let Some(report_id) = id_val.as_i64() else { return Err(WordstatError::BadResponse{ reason: "ReportID field is not an integer" }) };
-
-    let Some(status_val) = report.get("StatusReport") else { return Err(WordstatError::BadResponse{ reason: "No StatusReport field" }) };
-    let Value::String(status_str) = status_val else { return Err(WordstatError::BadResponse{ reason: "StatusReport field is not a string" }) };
-    let status = match status_str.as_str() {
-        "Done"      => { StatusCode::Done }
-        "Pending"   => { StatusCode::Pending }
-        "Failed"    => { StatusCode::Failed }
-        _           => { StatusCode::Unknown }
-    };
-
-    Ok(ReportStatus {
-        report_id,
-        status
-    })
+impl From<&ReportStatus> for ReportStatusModel {
+    fn from(report: &ReportStatus) -> Self {
+        let status = match report.status {
+            StatusCode::Done    => { "Done" }
+            StatusCode::Pending => { "Pending" }
+            StatusCode::Failed  => { "Failed" }
+            StatusCode::Unknown => { "Unknown" }
+        };
+
+        ReportStatusModel {
+            report_id: report.report_id,
+            status: status.to_string()
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::Value;
+    use crate::response::deserialize;
+    use crate::transport::MockTransport;
 
     #[test]
     fn parse_report() {
@@ -78,7 +89,7 @@ mod tests {
         let input: Value = serde_json::from_str(data).unwrap();
 
 
-        let received = super::parse_report(&input).unwrap();
+        let received = ReportStatus::from(deserialize::<ReportStatusModel, _>(&input).unwrap());
 
 
         let expected = ReportStatus {
@@ -97,7 +108,7 @@ mod tests {
         let input: Value = serde_json::from_str(data).unwrap();
 
 
-        let received = super::parse_report(&input).unwrap();
+        let received = ReportStatus::from(deserialize::<ReportStatusModel, _>(&input).unwrap());
 
 
         let expected = ReportStatus {
@@ -120,13 +131,14 @@ mod tests {
             "#;
         let return_value = serde_json::from_str(data).unwrap();
 
-        let mut mock_client = Client::default();
-        mock_client.expect_post()
-            .withf(|method, _params| method == "GetWordstatReportList")
-            .return_once(move |_method, _params| Ok(return_value));
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .withf(|_url, payload| payload["method"] == "GetWordstatReportList")
+            .return_once(move |_url, _payload| Ok(return_value));
+        let client = Client::with_transport("token", "api_url", transport);
 
 
-        let received = futures::executor::block_on(super::get_report_list(&mock_client)).unwrap();
+        let received = futures::executor::block_on(super::get_report_list(&client)).unwrap();
 
 
         let expected = vec![
@@ -150,4 +162,19 @@ mod tests {
 
         assert_eq!(received, expected)
     }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        let reports = vec![
+            ReportStatus { report_id: 54312, status: StatusCode::Done },
+            ReportStatus { report_id: 5424, status: StatusCode::Unknown }];
+
+
+        let serialized = serde_json::to_string(&reports).unwrap();
+        let received: Vec<ReportStatus> = serde_json::from_str(&serialized).unwrap();
+
+
+        assert_eq!(received, reports)
+    }
 }
@@ -0,0 +1,485 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use futures::stream::{self, Stream};
+use crate::WordstatError;
+use crate::client::Client;
+use crate::create_report::{ReportRequest, create_report};
+use crate::delete_report::delete_report;
+use crate::get_report::{ReportEntry, get_report};
+use crate::report_list::{StatusCode, get_report_list};
+
+/// The amount of reports the server stores simultaneously
+const MAX_LIVE_REPORTS: usize = 5;
+
+/// The result of a single [ReportRequest] processed by the [ReportManager]
+#[derive(Debug)]
+pub struct ReportOutcome {
+    /// Position of the request in the order it was added to the manager
+    pub index: usize,
+    /// The downloaded report or the error that prevented getting it
+    pub result: Result<Vec<ReportEntry>, WordstatError>
+}
+
+/// Runs any number of [ReportRequest]s while keeping no more than five reports
+/// on the server at the same time.
+///
+/// Every report is created, polled with [get_report_list] until it is
+/// [Done](StatusCode::Done), downloaded with [get_report] and deleted with
+/// [delete_report]. The results are streamed back as soon as they are ready.
+/// ```rust,ignore
+/// let mut results = ReportManager::new(&client)
+///     .with_requests(requests)
+///     .with_max_resubmits(2)
+///     .run();
+/// while let Some(outcome) = results.next().await {
+///     let outcome = outcome?;
+///     println!("Request {} finished: {:?}", outcome.index, outcome.result);
+/// }
+/// ```
+pub struct ReportManager<'a> {
+    client: &'a Client,
+    pending: VecDeque<QueuedRequest>,
+    live: Vec<LiveReport>,
+    ready: VecDeque<ReportOutcome>,
+    next_index: usize,
+    max_live_reports: usize,
+    poll_interval: Duration,
+    max_resubmits: u32,
+    max_queue_wait: Duration,
+    queue_full_since: Option<Instant>,
+    undeleted: Vec<i64>,
+    finished: bool
+}
+
+struct QueuedRequest {
+    index: usize,
+    request: ReportRequest,
+    resubmits: u32
+}
+
+struct LiveReport {
+    report_id: i64,
+    queued: QueuedRequest
+}
+
+impl<'a> ReportManager<'a> {
+    /// Creates a manager with no requests, polling every 10 seconds,
+    /// not resubmitting failed reports and waiting up to 10 minutes for a full queue.
+    pub fn new(client: &'a Client) -> Self {
+        ReportManager {
+            client,
+            pending: VecDeque::new(),
+            live: vec![],
+            ready: VecDeque::new(),
+            next_index: 0,
+            max_live_reports: MAX_LIVE_REPORTS,
+            poll_interval: Duration::from_secs(10),
+            max_resubmits: 0,
+            max_queue_wait: Duration::from_secs(10 * 60),
+            queue_full_since: None,
+            undeleted: vec![],
+            finished: false
+        }
+    }
+
+    /// Queues a request. Its [index](ReportOutcome::index) is the amount of
+    /// requests added before it.
+    pub fn add_request(mut self, request: ReportRequest) -> Self {
+        self.pending.push_back(QueuedRequest { index: self.next_index, request, resubmits: 0 });
+        self.next_index += 1;
+        self
+    }
+
+    /// Same as [add_request](ReportManager::add_request) but takes several requests at once.
+    pub fn with_requests(mut self, requests: Vec<ReportRequest>) -> Self {
+        for request in requests {
+            self = self.add_request(request);
+        }
+        self
+    }
+
+    /// Returns the amount of requests added to the manager
+    pub(crate) fn request_count(&self) -> usize {
+        self.next_index
+    }
+
+    /// Limits the amount of reports kept on the server by the manager.
+    /// Can not exceed the server limit of five reports. Lower it if other
+    /// programs use the same account.
+    pub fn with_max_live_reports(mut self, max_live_reports: usize) -> Self {
+        self.max_live_reports = max_live_reports.clamp(1, MAX_LIVE_REPORTS);
+        self
+    }
+
+    /// Sets the time to wait between checking the report statuses.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets how many times a report is created again after its status turns
+    /// [Failed](StatusCode::Failed). Once the limit is reached the request
+    /// ends with [ReportFailed](WordstatError::ReportFailed).
+    pub fn with_max_resubmits(mut self, max_resubmits: u32) -> Self {
+        self.max_resubmits = max_resubmits;
+        self
+    }
+
+    /// Sets how long to wait while the server queue is taken by reports the manager
+    /// doesn't know about. Once it runs out the stream yields
+    /// [ReportQueueFull](WordstatError::ReportQueueFull) and ends.
+    pub fn with_max_queue_wait(mut self, max_queue_wait: Duration) -> Self {
+        self.max_queue_wait = max_queue_wait;
+        self
+    }
+
+    /// Starts processing the requests and returns a stream of their outcomes,
+    /// in the order the reports become ready.
+    ///
+    /// Errors concerning a single request are returned in its [ReportOutcome].
+    /// If the report list can not be fetched, the stream yields the error and ends
+    /// after trying to delete the reports still in progress.
+    /// Reports are deleted from the server even if downloading them fails.
+    pub fn run(self) -> impl Stream<Item = Result<ReportOutcome, WordstatError>> + 'a {
+        stream::unfold(self, |mut manager| async move {
+            let item = manager.next_outcome().await?;
+            Some((item, manager))
+        })
+    }
+
+    /// Returns the next outcome, same as the [run](ReportManager::run) stream
+    pub(crate) async fn next_outcome(&mut self) -> Option<Result<ReportOutcome, WordstatError>> {
+        if self.finished { return None; }
+        loop {
+            if let Some(outcome) = self.ready.pop_front() { return Some(Ok(outcome)); }
+            if self.live.is_empty() && self.pending.is_empty() {
+                self.delete_undeleted().await;
+                return None;
+            }
+
+            if let Err(error) = self.submit_pending().await {
+                self.abandon().await;
+                return Some(Err(error));
+            }
+            if !self.ready.is_empty() { continue; }
+
+            self.client.clock().sleep(self.poll_interval).await;
+            if self.live.is_empty() { continue; }
+            if let Err(error) = self.check_live_reports().await {
+                self.abandon().await;
+                return Some(Err(error));
+            }
+        }
+    }
+
+    /// Creates reports for the queued requests while there are free slots.
+    /// Returns the queue full error once the queue has been full for too long
+    /// with none of the slots taken by the manager.
+    async fn submit_pending(&mut self) -> Result<(), WordstatError> {
+        self.delete_undeleted().await;
+        while self.live.len() < self.max_live_reports {
+            let Some(queued) = self.pending.pop_front() else { break };
+            match create_report(self.client, &queued.request).await {
+                Ok(report_id) => {
+                    self.queue_full_since = None;
+                    self.live.push(LiveReport { report_id, queued });
+                }
+                Err(error @ WordstatError::ReportQueueFull { .. }) => {
+                    // Slots are taken by reports the manager doesn't know about,
+                    // wait for them to be freed
+                    self.pending.push_front(queued);
+                    if !self.live.is_empty() { break; }
+                    let now = self.client.clock().now();
+                    let since = *self.queue_full_since.get_or_insert(now);
+                    if now.duration_since(since) >= self.max_queue_wait { return Err(error); }
+                    break;
+                }
+                Err(error) => { self.ready.push_back(ReportOutcome { index: queued.index, result: Err(error) }); }
+            }
+        }
+        Ok(())
+    }
+
+    /// Downloads finished reports and resubmits failed ones
+    async fn check_live_reports(&mut self) -> Result<(), WordstatError> {
+        let statuses = get_report_list(self.client).await?;
+
+        let mut still_live = vec![];
+        for live in std::mem::take(&mut self.live) {
+            let status = statuses.iter()
+                .find(|status| status.report_id == live.report_id)
+                .map(|status| &status.status);
+            match status {
+                Some(StatusCode::Done) => {
+                    let result = self.download(live.report_id).await;
+                    self.ready.push_back(ReportOutcome { index: live.queued.index, result });
+                }
+                Some(StatusCode::Failed) => {
+                    // Failed reports still take a slot on the server
+                    self.delete(live.report_id).await;
+                    let mut queued = live.queued;
+                    if queued.resubmits < self.max_resubmits {
+                        queued.resubmits += 1;
+                        self.pending.push_front(queued);
+                    }
+                    else {
+                        let result = Err(WordstatError::ReportFailed { report_id: live.report_id });
+                        self.ready.push_back(ReportOutcome { index: queued.index, result });
+                    }
+                }
+                Some(_) => { still_live.push(live); }
+                None => {
+                    let result = Err(WordstatError::ReportNotFound { report_id: live.report_id });
+                    self.ready.push_back(ReportOutcome { index: live.queued.index, result });
+                }
+            }
+        }
+        self.live = still_live;
+
+        Ok(())
+    }
+
+    /// Downloads the report and deletes it, whether the download succeeded or not
+    async fn download(&mut self, report_id: i64) -> Result<Vec<ReportEntry>, WordstatError> {
+        let report = get_report(self.client, report_id).await;
+        self.delete(report_id).await;
+        report
+    }
+
+    /// Deletes the report, remembering it to try again later if that fails
+    async fn delete(&mut self, report_id: i64) {
+        match delete_report(self.client, report_id).await {
+            Ok(()) | Err(WordstatError::ReportDoesNotExist { .. }) => {}
+            Err(_) => { self.undeleted.push(report_id); }
+        }
+    }
+
+    /// Stops processing after a fatal error, deleting the reports in progress
+    async fn abandon(&mut self) {
+        self.finished = true;
+        for live in std::mem::take(&mut self.live) {
+            self.delete(live.report_id).await;
+        }
+    }
+
+    /// Returns the IDs of the reports left on the server because deleting them failed
+    pub(crate) fn take_undeleted(&mut self) -> Vec<i64> {
+        std::mem::take(&mut self.undeleted)
+    }
+
+    /// Tries to delete the reports that could not be deleted before
+    async fn delete_undeleted(&mut self) {
+        for report_id in std::mem::take(&mut self.undeleted) {
+            self.delete(report_id).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use futures::StreamExt;
+    use serde_json::{Value, json};
+    use crate::clock::ManualClock;
+    use crate::mock_server::MockServer;
+    use crate::transport::MockTransport;
+
+    /// Fake server state: report ID, phrase and the statuses it goes through on each poll
+    #[derive(Default)]
+    struct Server {
+        next_id: i64,
+        reports: Vec<(i64, String, Vec<&'static str>)>,
+        max_live: usize,
+        created: usize
+    }
+
+    fn client(server: Arc<Mutex<Server>>, statuses: Vec<Vec<&'static str>>) -> Client {
+        let mut statuses: VecDeque<Vec<&'static str>> = statuses.into();
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .returning(move |_url, payload| {
+                let mut server = server.lock().unwrap();
+                let param = payload.get("param").cloned().unwrap_or(Value::Null);
+                let response = match payload["method"].as_str().unwrap() {
+                    "CreateNewWordstatReport" => {
+                        if server.reports.len() >= 5 { return Ok(json!({"error_code": 31})); }
+                        server.next_id += 1;
+                        server.created += 1;
+                        let id = server.next_id;
+                        let phrase = param["Phrases"][0].as_str().unwrap().to_string();
+                        server.reports.push((id, phrase, statuses.pop_front().unwrap()));
+                        server.max_live = server.max_live.max(server.reports.len());
+                        json!({"data": id})
+                    }
+                    "GetWordstatReportList" => {
+                        let list: Vec<Value> = server.reports.iter_mut()
+                            .map(|(id, _, states)| {
+                                let state = if states.len() > 1 { states.remove(0) } else { states[0] };
+                                json!({"ReportID": id, "StatusReport": state})
+                            })
+                            .collect();
+                        json!({"data": list})
+                    }
+                    "GetWordstatReport" => {
+                        let (_, phrase, _) = server.reports.iter().find(|(id, _, _)| *id == param.as_i64().unwrap()).unwrap();
+                        json!({"data": [{"Phrase": phrase, "GeoID": [], "SearchedWith": []}]})
+                    }
+                    "DeleteWordstatReport" => {
+                        server.reports.retain(|(id, _, _)| *id != param.as_i64().unwrap());
+                        json!({"data": 1})
+                    }
+                    _ => { panic!("Unexpected method") }
+                };
+                Ok(response)
+            });
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_clock(ManualClock::new());
+        client
+    }
+
+    fn request(phrase: &str) -> ReportRequest {
+        ReportRequest::new().add_phrase(phrase).unwrap()
+    }
+
+    #[test]
+    fn respects_report_limit() {
+        let server = Arc::new(Mutex::new(Server::default()));
+        let phrases = ["a", "b", "c", "d", "e", "f", "g"];
+        let statuses = phrases.iter().map(|_| vec!["Pending", "Done"]).collect();
+        let client = client(server.clone(), statuses);
+        let manager = ReportManager::new(&client)
+            .with_requests(phrases.iter().map(|phrase| request(phrase)).collect());
+
+
+        let mut received: Vec<(usize, String)> = futures::executor::block_on(manager.run().collect::<Vec<_>>())
+            .into_iter()
+            .map(|outcome| outcome.unwrap())
+            .map(|outcome| (outcome.index, outcome.result.unwrap().remove(0).phrase))
+            .collect();
+        received.sort();
+
+
+        let expected: Vec<(usize, String)> = phrases.iter().enumerate()
+            .map(|(index, phrase)| (index, phrase.to_string()))
+            .collect();
+        assert_eq!(received, expected);
+        let server = server.lock().unwrap();
+        assert_eq!(server.max_live, 5);
+        assert!(server.reports.is_empty())
+    }
+
+    #[test]
+    fn resubmits_failed_reports() {
+        let server = Arc::new(Mutex::new(Server::default()));
+        let statuses = vec![vec!["Failed"], vec!["Failed"], vec!["Done"]];
+        let client = client(server.clone(), statuses);
+        let manager = ReportManager::new(&client)
+            .add_request(request("rust"))
+            .with_max_resubmits(2);
+
+
+        let received = futures::executor::block_on(manager.run().collect::<Vec<_>>());
+
+
+        assert_eq!(received.len(), 1);
+        let outcome = received.into_iter().next().unwrap().unwrap();
+        assert_eq!(outcome.result.unwrap()[0].phrase, "rust");
+        let server = server.lock().unwrap();
+        assert_eq!(server.created, 3)
+    }
+
+    #[test]
+    fn gives_up_on_failed_reports() {
+        let server = Arc::new(Mutex::new(Server::default()));
+        let statuses = vec![vec!["Failed"], vec!["Failed"]];
+        let client = client(server.clone(), statuses);
+        let manager = ReportManager::new(&client)
+            .add_request(request("rust"))
+            .with_max_resubmits(1);
+
+
+        let received = futures::executor::block_on(manager.run().collect::<Vec<_>>());
+
+
+        assert_eq!(received.len(), 1);
+        let outcome = received.into_iter().next().unwrap().unwrap();
+        assert!(matches!(outcome.result, Err(WordstatError::ReportFailed { report_id: 2 })));
+        let server = server.lock().unwrap();
+        assert!(server.reports.is_empty())
+    }
+
+    #[tokio::test]
+    async fn deletes_reports_after_errors() {
+        let server = MockServer::start();
+        let client = Client::new(&server.token(), &server.url());
+        server.inject_error("GetWordstatReport", 500);
+        server.inject_error("DeleteWordstatReport", 500);
+        let manager = ReportManager::new(&client)
+            .with_requests(vec![request("rust"), request("cargo")])
+            .with_max_live_reports(1)
+            .with_poll_interval(Duration::from_millis(1));
+
+
+        let received: Vec<ReportOutcome> = manager.run().map(|outcome| outcome.unwrap()).collect().await;
+
+
+        assert!(matches!(received[0].result, Err(WordstatError::InternalServerError { .. })));
+        assert_eq!(received[1].result.as_ref().unwrap()[0].phrase, "cargo");
+        assert!(server.report_ids().is_empty())
+    }
+
+    #[tokio::test]
+    async fn keeps_entries_when_delete_fails() {
+        let server = MockServer::start();
+        let client = Client::new(&server.token(), &server.url());
+        server.inject_error("DeleteWordstatReport", 500);
+        let manager = ReportManager::new(&client)
+            .add_request(request("rust"))
+            .with_poll_interval(Duration::from_millis(1));
+
+
+        let received: Vec<ReportOutcome> = manager.run().map(|outcome| outcome.unwrap()).collect().await;
+
+
+        assert_eq!(received[0].result.as_ref().unwrap()[0].phrase, "rust");
+        assert!(server.report_ids().is_empty())
+    }
+
+    #[tokio::test]
+    async fn deletes_live_reports_on_fatal_error() {
+        let server = MockServer::start();
+        let client = Client::new(&server.token(), &server.url());
+        server.inject_error("GetWordstatReportList", 500);
+        let manager = ReportManager::new(&client)
+            .add_request(request("rust"))
+            .with_poll_interval(Duration::from_millis(1));
+
+
+        let received: Vec<Result<ReportOutcome, WordstatError>> = manager.run().collect().await;
+
+
+        assert!(matches!(received[..], [Err(WordstatError::InternalServerError { .. })]));
+        assert!(server.report_ids().is_empty())
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_full_queue() {
+        let server = MockServer::start();
+        let client = Client::new(&server.token(), &server.url());
+        for phrase in ["a", "b", "c", "d", "e"] {
+            create_report(&client, &request(phrase)).await.unwrap();
+        }
+        let manager = ReportManager::new(&client)
+            .add_request(request("rust"))
+            .with_poll_interval(Duration::from_millis(1))
+            .with_max_queue_wait(Duration::from_millis(20));
+
+
+        let received: Vec<Result<ReportOutcome, WordstatError>> = manager.run().collect().await;
+
+
+        assert_eq!(received.len(), 1);
+        assert!(matches!(received[0], Err(WordstatError::ReportQueueFull { .. })))
+    }
+}
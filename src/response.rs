@@ -0,0 +1,120 @@
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use crate::{ApiError, WordstatError, error_from_api};
+
+/// The envelope of every API response: either the `data` field or the error description
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiResponse<T> {
+    data: Option<T>,
+    error_code: Option<i64>,
+    error_str: Option<String>,
+    error_detail: Option<String>
+}
+
+impl<T> ApiResponse<T> {
+    /// Returns the API error described in the response, if there is one
+    pub(crate) fn error(&self) -> Option<WordstatError> {
+        let code = self.error_code?;
+        Some(error_from_api(ApiError {
+            code,
+            message: self.error_str.clone().unwrap_or_default(),
+            detail: self.error_detail.clone().unwrap_or_default()
+        }))
+    }
+
+    /// Returns the data of a successful response
+    pub(crate) fn into_data(self) -> Result<T, WordstatError> {
+        if let Some(error) = self.error() { return Err(error); }
+        let Some(data) = self.data else { return Err(bad_response("data", "missing field `data`")) };
+        Ok(data)
+    }
+}
+
+/// Deserializes the response body in one pass and returns its data
+pub(crate) fn parse_response<T: DeserializeOwned>(response: &[u8]) -> Result<T, WordstatError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(response);
+    let response: ApiResponse<T> = deserialize(&mut deserializer)?;
+    deserializer.end().map_err(|error| bad_response(".", &error.to_string()))?;
+    response.into_data()
+}
+
+/// Deserializes a JSON value, reporting the path to the first field that did not match
+pub(crate) fn deserialize<'de, T, D>(value: D) -> Result<T, WordstatError>
+    where T: Deserialize<'de>, D: serde::Deserializer<'de, Error = serde_json::Error> {
+    serde_path_to_error::deserialize(value)
+        .map_err(|error| bad_response(&error.path().to_string(), &error.inner().to_string()))
+}
+
+pub(crate) fn bad_response(path: &str, reason: &str) -> WordstatError {
+    WordstatError::BadResponse { path: path.to_string(), reason: reason.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        #[serde(rename = "Shows")]
+        shows: i64
+    }
+
+    #[test]
+    fn parse_data_field() {
+        let input = json!({"data": [{"Shows": 5}, {"Shows": 7}]}).to_string();
+
+
+        let received: Vec<Item> = parse_response(input.as_bytes()).unwrap();
+
+
+        assert_eq!(received, vec![Item { shows: 5 }, Item { shows: 7 }])
+    }
+
+    #[test]
+    fn parse_error_code() {
+        let input = json!({"error_code": 53, "error_str": "Authorization error", "error_detail": ""}).to_string();
+
+
+        let received = parse_response::<Vec<Item>>(input.as_bytes());
+
+
+        let Err(WordstatError::AuthorizationError { error }) = received else { panic!("Expected AuthorizationError") };
+        assert_eq!(error.message, "Authorization error")
+    }
+
+    #[test]
+    fn report_path_to_bad_field() {
+        let input = json!({"data": [{"Shows": 5}, {"Shows": "many"}]}).to_string();
+
+
+        let received = parse_response::<Vec<Item>>(input.as_bytes());
+
+
+        let Err(WordstatError::BadResponse { path, reason }) = received else { panic!("Expected BadResponse") };
+        assert_eq!(path, "data[1].Shows");
+        assert!(reason.contains("invalid type"))
+    }
+
+    #[test]
+    fn missing_data() {
+        let input = json!({}).to_string();
+
+
+        let received = parse_response::<Vec<Item>>(input.as_bytes());
+
+
+        assert!(matches!(received, Err(WordstatError::BadResponse { path, .. }) if path == "data"))
+    }
+
+    #[test]
+    fn trailing_characters() {
+        let input = r#"{"data": []} {"#;
+
+
+        let received = parse_response::<Vec<Item>>(input.as_bytes());
+
+
+        assert!(matches!(received, Err(WordstatError::BadResponse { path, .. }) if path == "."))
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::WordstatError;
+
+/// API methods that must not be sent twice unless the caller explicitly allows it,
+/// because every call creates a new report.
+const NON_IDEMPOTENT_METHODS: [&str; 1] = ["CreateNewWordstatReport"];
+
+/// Describes how the [Client](crate::client::Client) retries failed requests.
+///
+/// Delays grow exponentially from `base_delay` and are capped at `max_delay`.
+/// By default only transient errors are retried (see [is_transient](RetryPolicy::is_transient))
+/// and `CreateNewWordstatReport` is never retried.
+/// ```
+/// # use wordstat_rs::*;
+/// # use std::time::Duration;
+/// let policy = RetryPolicy::new()
+///     .with_max_attempts(5)
+///     .with_base_delay(Duration::from_millis(500))
+///     .with_max_delay(Duration::from_secs(10))
+///     .with_jitter(0.2);
+/// let mut client = Client::new("token", "api_url");
+/// client.set_retry_policy(policy);
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retry_non_idempotent: bool,
+    predicate: Arc<dyn Fn(&WordstatError) -> bool + Send + Sync>
+}
+
+impl RetryPolicy {
+    /// Creates a policy making up to 3 attempts with delays starting at 1 second
+    /// and capped at 30 seconds, with 10% jitter.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.1,
+            retry_non_idempotent: false,
+            predicate: Arc::new(RetryPolicy::is_transient)
+        }
+    }
+
+    /// Creates a policy that never retries. This is the client's default.
+    pub fn none() -> Self {
+        RetryPolicy::new().with_max_attempts(1)
+    }
+
+    /// Sets the total number of attempts, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the upper bound for the delay between attempts.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets the share of the delay (from 0 to 1) that is randomized, so that
+    /// several clients do not retry at the same moment.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Allows retrying `CreateNewWordstatReport`. A retried request may create
+    /// a duplicate report if the first one reached the server.
+    pub fn with_retry_non_idempotent(mut self, retry: bool) -> Self {
+        self.retry_non_idempotent = retry;
+        self
+    }
+
+    /// Replaces the check that decides which errors are worth retrying.
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+        where F: Fn(&WordstatError) -> bool + Send + Sync + 'static {
+        self.predicate = Arc::new(predicate);
+        self
+    }
+
+    /// The default retry predicate, see [WordstatError::is_retryable].
+    pub fn is_transient(error: &WordstatError) -> bool {
+        error.is_retryable()
+    }
+
+    /// Returns true if another attempt should be made after `attempt` attempts
+    /// of `method` have failed with `error`.
+    pub fn should_retry(&self, method: &str, attempt: u32, error: &WordstatError) -> bool {
+        if attempt >= self.max_attempts { return false; }
+        if !self.retry_non_idempotent && NON_IDEMPOTENT_METHODS.contains(&method) { return false; }
+        (self.predicate)(error)
+    }
+
+    /// Returns the delay to wait after `attempt` attempts have failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+        if self.jitter == 0.0 { return delay; }
+        delay.mul_f64(1.0 - self.jitter * fastrand::f64())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .field("retry_non_idempotent", &self.retry_non_idempotent)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use crate::client::Client;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new()
+            .with_max_attempts(4)
+            .with_base_delay(Duration::from_secs(1))
+            .with_max_delay(Duration::from_secs(3))
+            .with_jitter(0.0)
+    }
+
+    #[test]
+    fn exponential_delay() {
+        let policy = policy();
+
+
+        let received: Vec<Duration> = (1..=4).map(|attempt| policy.delay(attempt)).collect();
+
+
+        let expected = vec![
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            Duration::from_secs(3),
+            Duration::from_secs(3)
+        ];
+        assert_eq!(received, expected)
+    }
+
+    #[test]
+    fn jitter_shortens_delay() {
+        let policy = policy().with_jitter(0.5);
+
+
+        let received = policy.delay(2);
+
+
+        assert!(received >= Duration::from_secs(1) && received <= Duration::from_secs(2))
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let clock = ManualClock::new();
+        let mut transport = MockTransport::new();
+        let mut responses = vec![
+            Ok(serde_json::json!({"data": 1})),
+            Ok(serde_json::json!({"error_code": 31})),
+            Err(WordstatError::HttpStatus { status: 502 })
+        ];
+        transport.expect_post()
+            .times(3)
+            .returning(move |_url, _payload| responses.pop().unwrap());
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_retry_policy(policy());
+        client.set_clock(clock.clone());
+
+
+        let received = futures::executor::block_on(client.post("GetWordstatReport", Some(Value::from(5)))).unwrap();
+
+
+        assert_eq!(received, serde_json::json!({"data": 1}));
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(1), Duration::from_secs(2)])
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let clock = ManualClock::new();
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .times(4)
+            .returning(|_url, _payload| Ok(serde_json::json!({"error_code": 500})));
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_retry_policy(policy());
+        client.set_clock(clock.clone());
+
+
+        let received = futures::executor::block_on(client.post("GetRegions", None));
+
+
+        assert!(matches!(received, Err(WordstatError::InternalServerError { .. })));
+        assert_eq!(clock.sleeps().len(), 3)
+    }
+
+    #[test]
+    fn does_not_retry_permanent_errors() {
+        let clock = ManualClock::new();
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .times(1)
+            .returning(|_url, _payload| Ok(serde_json::json!({"error_code": 53})));
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_retry_policy(policy());
+        client.set_clock(clock.clone());
+
+
+        let received = futures::executor::block_on(client.post("GetRegions", None));
+
+
+        assert!(matches!(received, Err(WordstatError::AuthorizationError { .. })));
+        assert!(clock.sleeps().is_empty())
+    }
+
+    #[test]
+    fn does_not_retry_report_creation() {
+        let clock = ManualClock::new();
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .times(1)
+            .returning(|_url, _payload| Ok(serde_json::json!({"error_code": 500})));
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_retry_policy(policy());
+        client.set_clock(clock.clone());
+
+
+        let received = futures::executor::block_on(client.post("CreateNewWordstatReport", None));
+
+
+        assert!(matches!(received, Err(WordstatError::InternalServerError { .. })));
+        assert!(clock.sleeps().is_empty())
+    }
+
+    #[test]
+    fn retries_report_creation_when_allowed() {
+        let clock = ManualClock::new();
+        let mut transport = MockTransport::new();
+        let mut responses = vec![
+            Ok(serde_json::json!({"data": 1})),
+            Ok(serde_json::json!({"error_code": 31}))
+        ];
+        transport.expect_post()
+            .times(2)
+            .returning(move |_url, _payload| responses.pop().unwrap());
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_retry_policy(policy().with_retry_non_idempotent(true));
+        client.set_clock(clock.clone());
+
+
+        let received = futures::executor::block_on(client.post("CreateNewWordstatReport", None)).unwrap();
+
+
+        assert_eq!(received, serde_json::json!({"data": 1}));
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(1)])
+    }
+
+    #[test]
+    fn custom_predicate() {
+        let clock = ManualClock::new();
+        let mut transport = MockTransport::new();
+        let mut responses = vec![
+            Ok(serde_json::json!({"data": 1})),
+            Ok(serde_json::json!({"error_code": 152}))
+        ];
+        transport.expect_post()
+            .times(2)
+            .returning(move |_url, _payload| responses.pop().unwrap());
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_retry_policy(policy().with_predicate(|error| error.is_quota()));
+        client.set_clock(clock.clone());
+
+
+        let received = futures::executor::block_on(client.post("GetRegions", None)).unwrap();
+
+
+        assert_eq!(received, serde_json::json!({"data": 1}))
+    }
+}
@@ -0,0 +1,386 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rusqlite::{Connection, OptionalExtension, params};
+use crate::{DatabaseError, WordstatError};
+use crate::get_report::{ReportEntry, WordstatItem};
+use crate::region::Region;
+
+/// The schema changes applied in order. The amount of applied migrations is kept
+/// in `PRAGMA user_version`, so never edit or reorder the existing ones.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE runs (
+        id          INTEGER PRIMARY KEY,
+        fetched_at  INTEGER NOT NULL
+    );
+    CREATE TABLE entries (
+        id          INTEGER PRIMARY KEY,
+        run_id      INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+        position    INTEGER NOT NULL,
+        phrase      TEXT NOT NULL,
+        geo_id      TEXT NOT NULL
+    );
+    CREATE TABLE items (
+        id          INTEGER PRIMARY KEY,
+        entry_id    INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+        kind        TEXT NOT NULL CHECK (kind IN ('searched_with', 'searched_also')),
+        position    INTEGER NOT NULL,
+        phrase      TEXT NOT NULL,
+        shows       INTEGER NOT NULL
+    );
+    CREATE TABLE regions (
+        id          INTEGER PRIMARY KEY,
+        name        TEXT NOT NULL,
+        parent_id   INTEGER
+    );
+    CREATE INDEX entries_run ON entries(run_id);
+    CREATE INDEX items_entry ON items(entry_id);
+    CREATE INDEX items_phrase ON items(phrase);"
+];
+
+/// A saved report run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportRun {
+    /// The ID of the run in the database
+    pub id: i64,
+    /// The time the reports were downloaded
+    pub fetched_at: SystemTime
+}
+
+/// The amount of searches of a phrase in one of the runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShowsSnapshot {
+    /// The ID of the run
+    pub run_id: i64,
+    /// The time the run was downloaded
+    pub fetched_at: SystemTime,
+    /// The regions of the report entry the phrase was found in
+    pub geo_id: Vec<i64>,
+    /// The amount of searches
+    pub shows: i64
+}
+
+/// Keeps downloaded reports and regions in an SQLite database, so that the amount
+/// of searches can be tracked from run to run.
+///
+/// The schema is created or upgraded when the database is opened.
+/// ```rust,ignore
+/// let mut storage = SqliteStorage::open("wordstat.db")?;
+/// storage.insert_run(&get_report(&client, report_id).await?, SystemTime::now())?;
+/// for snapshot in storage.shows_history("rust lang")? {
+///     println!("{:?}: {}", snapshot.fetched_at, snapshot.shows);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection
+}
+
+impl SqliteStorage {
+    /// Opens or creates the database file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, WordstatError> {
+        SqliteStorage::with_connection(Connection::open(path)?)
+    }
+
+    /// Creates a database that lives in memory until the storage is dropped
+    pub fn open_in_memory() -> Result<Self, WordstatError> {
+        SqliteStorage::with_connection(Connection::open_in_memory()?)
+    }
+
+    /// Uses an already opened connection, applying the missing migrations
+    pub fn with_connection(connection: Connection) -> Result<Self, WordstatError> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        let mut storage = SqliteStorage { connection };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    /// Returns the amount of applied migrations
+    pub fn schema_version(&self) -> Result<i64, WordstatError> {
+        Ok(self.connection.pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    fn migrate(&mut self) -> Result<(), WordstatError> {
+        let version = self.schema_version()?;
+        if version > MIGRATIONS.len() as i64 {
+            return Err(WordstatError::UnsupportedSchema { version });
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let transaction = self.connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index as i64 + 1)?;
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Saves the report entries as a new run and returns its ID
+    pub fn insert_run(&mut self, entries: &[ReportEntry], fetched_at: SystemTime) -> Result<i64, WordstatError> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("INSERT INTO runs (fetched_at) VALUES (?1)", params![to_unix(fetched_at)])?;
+        let run_id = transaction.last_insert_rowid();
+
+        for (position, entry) in entries.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO entries (run_id, position, phrase, geo_id) VALUES (?1, ?2, ?3, ?4)",
+                params![run_id, position, entry.phrase, serde_json::to_string(&entry.geo_id).unwrap()]
+            )?;
+            let entry_id = transaction.last_insert_rowid();
+            let items = entry.searched_with.iter().enumerate().map(|item| ("searched_with", item))
+                .chain(entry.searched_also.iter().enumerate().map(|item| ("searched_also", item)));
+            for (kind, (position, item)) in items {
+                transaction.execute(
+                    "INSERT INTO items (entry_id, kind, position, phrase, shows) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![entry_id, kind, position, item.phrase, item.shows]
+                )?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(run_id)
+    }
+
+    /// Returns all saved runs, oldest first
+    pub fn runs(&self) -> Result<Vec<ReportRun>, WordstatError> {
+        let mut statement = self.connection.prepare("SELECT id, fetched_at FROM runs ORDER BY fetched_at, id")?;
+        let runs = statement
+            .query_map([], |row| Ok(ReportRun { id: row.get(0)?, fetched_at: from_unix(row.get(1)?) }))?
+            .collect::<Result<_, _>>()?;
+        Ok(runs)
+    }
+
+    /// Returns the report entries of the run in the order they were saved.
+    /// Empty if there is no such run.
+    pub fn run_entries(&self, run_id: i64) -> Result<Vec<ReportEntry>, WordstatError> {
+        let mut entry_statement = self.connection.prepare(
+            "SELECT id, phrase, geo_id FROM entries WHERE run_id = ?1 ORDER BY position")?;
+        let mut item_statement = self.connection.prepare(
+            "SELECT kind, phrase, shows FROM items WHERE entry_id = ?1 ORDER BY kind, position")?;
+
+        let rows: Vec<(i64, String, String)> = entry_statement
+            .query_map([run_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        let mut entries = vec![];
+        for (entry_id, phrase, geo_id) in rows {
+            let mut entry = ReportEntry {
+                phrase,
+                geo_id: parse_geo_id(&geo_id)?,
+                searched_with: vec![],
+                searched_also: vec![]
+            };
+            let items = item_statement.query_map([entry_id], |row| {
+                Ok((row.get::<_, String>(0)?, WordstatItem { phrase: row.get(1)?, shows: row.get(2)? }))
+            })?;
+            for item in items {
+                let (kind, item) = item?;
+                match kind.as_str() {
+                    "searched_with" => { entry.searched_with.push(item) }
+                    _ => { entry.searched_also.push(item) }
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Deletes the run along with its entries
+    pub fn delete_run(&mut self, run_id: i64) -> Result<(), WordstatError> {
+        self.connection.execute("DELETE FROM runs WHERE id = ?1", [run_id])?;
+        Ok(())
+    }
+
+    /// Returns the amount of searches of the phrase in every run it was
+    /// [searched with](ReportEntry::searched_with), oldest first
+    pub fn shows_history(&self, phrase: &str) -> Result<Vec<ShowsSnapshot>, WordstatError> {
+        let mut statement = self.connection.prepare(
+            "SELECT runs.id, runs.fetched_at, entries.geo_id, items.shows
+             FROM items
+             JOIN entries ON entries.id = items.entry_id
+             JOIN runs ON runs.id = entries.run_id
+             WHERE items.phrase = ?1 AND items.kind = 'searched_with'
+             ORDER BY runs.fetched_at, runs.id, entries.position")?;
+        let rows: Vec<(i64, i64, String, i64)> = statement
+            .query_map([phrase], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<Result<_, _>>()?;
+
+        rows.into_iter()
+            .map(|(run_id, fetched_at, geo_id, shows)| Ok(ShowsSnapshot {
+                run_id,
+                fetched_at: from_unix(fetched_at),
+                geo_id: parse_geo_id(&geo_id)?,
+                shows
+            }))
+            .collect()
+    }
+
+    /// Replaces the saved regions with the passed ones
+    pub fn save_regions(&mut self, regions: &[Region]) -> Result<(), WordstatError> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM regions", [])?;
+        for region in regions {
+            transaction.execute(
+                "INSERT INTO regions (id, name, parent_id) VALUES (?1, ?2, ?3)",
+                params![region.id, region.name, region.parent_id]
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the saved regions ordered by ID
+    pub fn regions(&self) -> Result<Vec<Region>, WordstatError> {
+        let mut statement = self.connection.prepare("SELECT id, name, parent_id FROM regions ORDER BY id")?;
+        let regions = statement
+            .query_map([], |row| Ok(Region { id: row.get(0)?, name: row.get(1)?, parent_id: row.get(2)? }))?
+            .collect::<Result<_, _>>()?;
+        Ok(regions)
+    }
+
+    /// Returns the saved region with the passed ID
+    pub fn region(&self, id: i64) -> Result<Option<Region>, WordstatError> {
+        let region = self.connection
+            .query_row("SELECT id, name, parent_id FROM regions WHERE id = ?1", [id], |row| {
+                Ok(Region { id: row.get(0)?, name: row.get(1)?, parent_id: row.get(2)? })
+            })
+            .optional()?;
+        Ok(region)
+    }
+}
+
+impl From<rusqlite::Error> for WordstatError {
+    fn from(error: rusqlite::Error) -> Self {
+        WordstatError::Database { source: DatabaseError::new(error) }
+    }
+}
+
+fn parse_geo_id(geo_id: &str) -> Result<Vec<i64>, WordstatError> {
+    serde_json::from_str(geo_id).map_err(|error| WordstatError::Database { source: DatabaseError::new(error) })
+}
+
+fn to_unix(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|time| time.as_secs() as i64).unwrap_or_default()
+}
+
+fn from_unix(seconds: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(phrase: &str, shows: i64) -> WordstatItem {
+        WordstatItem { phrase: phrase.to_string(), shows }
+    }
+
+    fn entries(shows: i64) -> Vec<ReportEntry> {
+        vec![
+            ReportEntry {
+                phrase: "rust".to_string(),
+                geo_id: vec![213, -1],
+                searched_with: vec![item("rust", shows), item("rust lang", 10)],
+                searched_also: vec![item("cargo", 3)]
+            },
+            ReportEntry {
+                phrase: "go".to_string(),
+                geo_id: vec![],
+                searched_with: vec![item("go", 7)],
+                searched_also: vec![]
+            },
+        ]
+    }
+
+    fn time(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn round_trip_run() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let run_id = storage.insert_run(&entries(5), time(100)).unwrap();
+
+
+        let received = storage.run_entries(run_id).unwrap();
+
+
+        assert_eq!(received, entries(5));
+        assert_eq!(storage.runs().unwrap(), vec![ReportRun { id: run_id, fetched_at: time(100) }])
+    }
+
+    #[test]
+    fn shows_history() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let second = storage.insert_run(&entries(8), time(200)).unwrap();
+        let first = storage.insert_run(&entries(5), time(100)).unwrap();
+
+
+        let received = storage.shows_history("rust").unwrap();
+
+
+        let expected = vec![
+            ShowsSnapshot { run_id: first, fetched_at: time(100), geo_id: vec![213, -1], shows: 5 },
+            ShowsSnapshot { run_id: second, fetched_at: time(200), geo_id: vec![213, -1], shows: 8 },
+        ];
+        assert_eq!(received, expected);
+        assert!(storage.shows_history("cargo").unwrap().is_empty())
+    }
+
+    #[test]
+    fn delete_run() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let run_id = storage.insert_run(&entries(5), time(100)).unwrap();
+
+
+        storage.delete_run(run_id).unwrap();
+
+
+        assert!(storage.runs().unwrap().is_empty());
+        assert!(storage.run_entries(run_id).unwrap().is_empty());
+        assert!(storage.shows_history("rust").unwrap().is_empty())
+    }
+
+    #[test]
+    fn regions() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        let regions = vec![
+            Region { name: "All".to_string(), id: 0, parent_id: None },
+            Region { name: "Москва".to_string(), id: 213, parent_id: Some(0) },
+        ];
+        storage.save_regions(&[Region { name: "Old".to_string(), id: 5, parent_id: None }]).unwrap();
+
+
+        storage.save_regions(&regions).unwrap();
+
+
+        assert_eq!(storage.regions().unwrap(), regions);
+        assert_eq!(storage.region(213).unwrap().unwrap().name, "Москва");
+        assert!(storage.region(5).unwrap().is_none())
+    }
+
+    #[test]
+    fn reopen_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wordstat.db");
+        let run_id = SqliteStorage::open(&path).unwrap().insert_run(&entries(5), time(100)).unwrap();
+
+
+        let storage = SqliteStorage::open(&path).unwrap();
+
+
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len() as i64);
+        assert_eq!(storage.run_entries(run_id).unwrap(), entries(5))
+    }
+
+    #[test]
+    fn newer_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wordstat.db");
+        Connection::open(&path).unwrap().pragma_update(None, "user_version", 100).unwrap();
+
+
+        let received = SqliteStorage::open(&path);
+
+
+        assert!(matches!(received, Err(WordstatError::UnsupportedSchema { version: 100 })))
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use crate::{BoxedError, WordstatError};
+use crate::response::bad_response;
+
+/// The HTTP layer used by the [Client](crate::client::Client) to talk to the API.
+///
+/// The client builds the JSON payload (method, token and parameters) and hands it
+/// to the transport, which is responsible for delivering it to the API URL and
+/// returning the JSON response.
+///
+/// [ReqwestTransport] is used by default. Implement this trait to plug in another
+/// HTTP stack, a record/replay layer or an in-memory fake for tests:
+/// ```
+/// # use wordstat_rs::*;
+/// # use serde_json::{Value, json};
+/// struct FakeTransport;
+///
+/// #[async_trait::async_trait]
+/// impl Transport for FakeTransport {
+///     async fn post(&self, _url: &str, _payload: &Value) -> Result<Value, WordstatError> {
+///         Ok(json!({"data": 1}))
+///     }
+/// }
+///
+/// let client = Client::with_transport("token", "api_url", FakeTransport);
+/// ```
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Sends the payload to the passed URL and returns the JSON response.
+    async fn post(&self, url: &str, payload: &Value) -> Result<Value, WordstatError>;
+
+    /// Sends the payload to the passed URL and returns the raw response body.
+    ///
+    /// The client deserializes the body straight into the expected response type.
+    /// The default implementation serializes the value returned by [post](Transport::post),
+    /// transports reading the body from the network should override it.
+    async fn post_bytes(&self, url: &str, payload: &Value) -> Result<Vec<u8>, WordstatError> {
+        let response = self.post(url, payload).await?;
+        serde_json::to_vec(&response).map_err(|error| bad_response(".", &error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mockall::mock! {
+    pub(crate) Transport {}
+
+    #[async_trait]
+    impl Transport for Transport {
+        async fn post(&self, url: &str, payload: &Value) -> Result<Value, WordstatError>;
+    }
+}
+
+/// The stage of a request at which a [Transport](WordstatError::Transport) or
+/// [Timeout](WordstatError::Timeout) error has happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportErrorKind {
+    /// Failed to connect to the API (DNS failure, refused connection, TLS error)
+    Connect,
+    /// Failed to send the request or receive the response headers
+    Request,
+    /// Failed to read or decode the response body
+    Decode
+}
+
+impl fmt::Display for TransportErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportErrorKind::Connect => { write!(f, "connecting to the API") }
+            TransportErrorKind::Request => { write!(f, "sending the request") }
+            TransportErrorKind::Decode  => { write!(f, "reading the response body") }
+        }
+    }
+}
+
+/// The underlying cause of a [Transport](WordstatError::Transport) or
+/// [Timeout](WordstatError::Timeout) error, as reported by the transport
+pub type TransportError = BoxedError;
+
+/// Default [Transport] implementation backed by [reqwest]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+    headers: HeaderMap,
+    timeout: Option<Duration>
+}
+
+impl ReqwestTransport {
+    /// Creates a transport with a default reqwest client
+    pub fn new() -> Self {
+        ReqwestTransport::with_client(reqwest::Client::new())
+    }
+
+    /// Creates a transport using an already configured reqwest client
+    pub fn with_client(client: reqwest::Client) -> Self {
+        ReqwestTransport { client, headers: HeaderMap::new(), timeout: None }
+    }
+
+    /// Sets the headers added to every request
+    pub(crate) fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets the time limit for every request
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        ReqwestTransport::new()
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn post(&self, url: &str, payload: &Value) -> Result<Value, WordstatError> {
+        let response = self.post_bytes(url, payload).await?;
+        serde_json::from_slice(&response)
+            .map_err(|error| bad_response(".", &error.to_string()))
+    }
+
+    async fn post_bytes(&self, url: &str, payload: &Value) -> Result<Vec<u8>, WordstatError> {
+        let mut request = self.client.post(url)
+            .headers(self.headers.clone())
+            .json(payload);
+        if let Some(timeout) = self.timeout { request = request.timeout(timeout); }
+        let response = request
+            .send()
+            .await
+            .map_err(|error| transport_error(error, TransportErrorKind::Request))?;
+        check_http_status(response.status())?;
+
+        read_body(response.bytes().await)
+    }
+}
+
+/// Returns [HttpStatus](WordstatError::HttpStatus) unless the API answered with 200 OK
+pub(crate) fn check_http_status(status: StatusCode) -> Result<(), WordstatError> {
+    if status != StatusCode::OK {
+        return Err(WordstatError::HttpStatus { status: status.as_u16() });
+    }
+    Ok(())
+}
+
+/// Converts the result of reading the response body, shared by the async and blocking clients
+pub(crate) fn read_body<B: AsRef<[u8]>>(body: Result<B, reqwest::Error>) -> Result<Vec<u8>, WordstatError> {
+    body.map(|body| body.as_ref().to_vec())
+        .map_err(|error| transport_error(error, TransportErrorKind::Decode))
+}
+
+/// Converts a reqwest error into a [WordstatError], using `kind` unless the error
+/// itself tells that it happened while connecting.
+pub(crate) fn transport_error(error: reqwest::Error, kind: TransportErrorKind) -> WordstatError {
+    let kind = if error.is_connect() { TransportErrorKind::Connect } else { kind };
+    if error.is_timeout() {
+        WordstatError::Timeout { kind, source: TransportError::new(error) }
+    }
+    else {
+        WordstatError::Transport { kind, source: TransportError::new(error) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+
+    #[test]
+    fn client_builds_payload() {
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .withf(|url, payload| url == "api_url"
+                   && payload["method"] == "GetWordstatReport"
+                   && payload["token"] == "token"
+                   && payload["param"] == 54)
+            .return_once(|_url, _payload| Ok(serde_json::json!({"data": 1})));
+        let client = Client::with_transport("token", "api_url", transport);
+
+
+        let received = futures::executor::block_on(client.post("GetWordstatReport", Some(Value::from(54)))).unwrap();
+
+
+        assert_eq!(received, serde_json::json!({"data": 1}))
+    }
+
+    #[test]
+    fn client_omits_empty_params() {
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .withf(|_url, payload| payload.get("param").is_none())
+            .return_once(|_url, _payload| Ok(serde_json::json!({"data": 1})));
+        let client = Client::with_transport("token", "api_url", transport);
+
+
+        let received = futures::executor::block_on(client.post("GetRegions", None)).unwrap();
+
+
+        assert_eq!(received, serde_json::json!({"data": 1}))
+    }
+
+    #[tokio::test]
+    async fn refused_connection() {
+        // Grab a free port and close it right away so nothing listens on it
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let transport = ReqwestTransport::new();
+
+
+        let received = transport.post(&format!("http://127.0.0.1:{port}/"), &Value::Null).await;
+
+
+        assert!(matches!(received, Err(WordstatError::Transport { kind: TransportErrorKind::Connect, .. })))
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        // Accepts the connection but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(100))
+            .build().unwrap();
+        let transport = ReqwestTransport::with_client(client);
+
+
+        let received = transport.post(&format!("http://127.0.0.1:{port}/"), &Value::Null).await;
+
+
+        assert!(matches!(received, Err(WordstatError::Timeout { kind: TransportErrorKind::Request, .. })));
+        drop(listener)
+    }
+}
@@ -0,0 +1,315 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use futures::future::{self, Either};
+use tokio_util::sync::CancellationToken;
+use crate::WordstatError;
+use crate::client::Client;
+use crate::clock::Clock;
+use crate::get_report::{ReportEntry, get_report};
+use crate::report_list::{StatusCode, get_report_list};
+
+/// Describes how [wait_for_report] polls the report status.
+///
+/// The interval between polls starts at `interval` and is multiplied by `backoff`
+/// after every poll, up to `max_interval`.
+/// ```
+/// # use wordstat_rs::*;
+/// # use std::time::Duration;
+/// let token = CancellationToken::new();
+/// let options = PollOptions::new()
+///     .with_interval(Duration::from_secs(5))
+///     .with_backoff(1.5)
+///     .with_max_interval(Duration::from_secs(60))
+///     .with_timeout(Duration::from_secs(600))
+///     .with_cancellation(token.clone());
+/// ```
+#[derive(Debug, Clone)]
+pub struct PollOptions {
+    interval: Duration,
+    backoff: f64,
+    max_interval: Duration,
+    timeout: Option<Duration>,
+    cancellation: Option<CancellationToken>
+}
+
+impl PollOptions {
+    /// Creates options polling every 10 seconds without backoff, timeout or cancellation
+    pub fn new() -> Self {
+        PollOptions {
+            interval: Duration::from_secs(10),
+            backoff: 1.0,
+            max_interval: Duration::from_secs(10),
+            timeout: None,
+            cancellation: None
+        }
+    }
+
+    /// Sets the delay before the second poll. Also raises the maximum interval
+    /// if it is lower than the passed value.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self.max_interval = self.max_interval.max(interval);
+        self
+    }
+
+    /// Sets the factor the interval is multiplied by after every poll.
+    /// Values below 1 are treated as 1.
+    pub fn with_backoff(mut self, backoff: f64) -> Self {
+        self.backoff = backoff.max(1.0);
+        self
+    }
+
+    /// Sets the upper bound for the interval between polls.
+    pub fn with_max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Sets the overall time after which waiting ends with
+    /// [DeadlineExceeded](WordstatError::DeadlineExceeded).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the token that stops waiting with [Cancelled](WordstatError::Cancelled)
+    /// once cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(|token| token.is_cancelled())
+    }
+
+    /// Returns the interval before the second poll
+    pub(crate) fn first_interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the interval following the passed one
+    pub(crate) fn next_interval(&self, interval: Duration) -> Duration {
+        interval.mul_f64(self.backoff).min(self.max_interval)
+    }
+
+    /// Returns how long to sleep before the next poll, cut to the time left
+    /// until the timeout, or [DeadlineExceeded](WordstatError::DeadlineExceeded)
+    /// if the timeout has passed
+    pub(crate) fn delay(&self, interval: Duration, elapsed: Duration, report_id: i64) -> Result<Duration, WordstatError> {
+        let Some(timeout) = self.timeout else { return Ok(interval) };
+        if elapsed >= timeout { return Err(WordstatError::DeadlineExceeded { report_id }); }
+        Ok(interval.min(timeout - elapsed))
+    }
+
+    /// Runs the future until it completes, the token is cancelled or the timeout
+    /// counted from `started` passes
+    async fn bounded<T>(&self, clock: &dyn Clock, started: Instant, report_id: i64, future: impl Future<Output = Result<T, WordstatError>>) -> Result<T, WordstatError> {
+        let cancelled = async {
+            match &self.cancellation {
+                Some(token) => { token.cancelled().await }
+                None => { future::pending().await }
+            }
+        };
+        let deadline = async {
+            match self.timeout {
+                Some(timeout) => { clock.sleep(timeout.saturating_sub(clock.now().duration_since(started))).await }
+                None => { future::pending().await }
+            }
+        };
+        futures::pin_mut!(future, cancelled, deadline);
+        match future::select(future, future::select(cancelled, deadline)).await {
+            Either::Left((result, _)) => { result }
+            Either::Right((Either::Left(_), _)) => { Err(WordstatError::Cancelled) }
+            Either::Right((Either::Right(_), _)) => { Err(WordstatError::DeadlineExceeded { report_id }) }
+        }
+    }
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        PollOptions::new()
+    }
+}
+
+/// Polls [get_report_list] until the report with the passed ID is
+/// [Done](StatusCode::Done) and returns it.
+///
+/// Returns [ReportFailed](WordstatError::ReportFailed) if the report status turns
+/// [Failed](StatusCode::Failed). [ReportNotReady](WordstatError::ReportNotReady) from the
+/// server is treated as a reason to keep waiting. Cancellation and the timeout also
+/// interrupt requests that are in flight.
+pub async fn wait_for_report(client: &Client, report_id: i64, options: PollOptions) -> Result<Vec<ReportEntry>, WordstatError> {
+    let started = client.clock().now();
+    let mut interval = options.first_interval();
+
+    loop {
+        if options.is_cancelled() { return Err(WordstatError::Cancelled); }
+
+        let statuses = options.bounded(client.clock(), started, report_id, get_report_list(client)).await?;
+        let Some(status) = statuses.iter().find(|status| status.report_id == report_id) else { return Err(WordstatError::ReportNotFound { report_id }) };
+        match status.status {
+            StatusCode::Done => {
+                match options.bounded(client.clock(), started, report_id, get_report(client, report_id)).await {
+                    Err(WordstatError::ReportNotReady { .. }) => {}
+                    result => { return result; }
+                }
+            }
+            StatusCode::Failed => { return Err(WordstatError::ReportFailed { report_id }); }
+            _ => {}
+        }
+
+        let delay = options.delay(interval, client.clock().now().duration_since(started), report_id)?;
+        options.bounded(client.clock(), started, report_id, async {
+            client.clock().sleep(delay).await;
+            Ok(())
+        }).await?;
+        interval = options.next_interval(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+
+    const REPORT: &str = r#"{"data": [{"Phrase": "rust", "GeoID": [], "SearchedWith": [{"Phrase": "rust", "Shows": 5}]}]}"#;
+
+    /// Returns a client answering with the passed responses in order
+    fn client(clock: &ManualClock, responses: Vec<&str>) -> Client {
+        let mut responses: Vec<Value> = responses.into_iter()
+            .rev()
+            .map(|response| serde_json::from_str(response).unwrap())
+            .collect();
+        let mut transport = MockTransport::new();
+        transport.expect_post()
+            .times(responses.len())
+            .returning(move |_url, _payload| Ok(responses.pop().unwrap()));
+        let mut client = Client::with_transport("token", "api_url", transport);
+        client.set_clock(clock.clone());
+        client
+    }
+
+    fn status(state: &str) -> String {
+        json!({"data": [{"ReportID": 7, "StatusReport": "Done"}, {"ReportID": 5, "StatusReport": state}]}).to_string()
+    }
+
+    #[test]
+    fn waits_until_done() {
+        let clock = ManualClock::new();
+        let pending = status("Pending");
+        let done = status("Done");
+        let client = client(&clock, vec![&pending, &pending, &pending, &done, REPORT]);
+        let options = PollOptions::new()
+            .with_interval(Duration::from_secs(1))
+            .with_backoff(2.0)
+            .with_max_interval(Duration::from_secs(3));
+
+
+        let received = futures::executor::block_on(wait_for_report(&client, 5, options)).unwrap();
+
+
+        assert_eq!(received[0].phrase, "rust");
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(3)])
+    }
+
+    #[test]
+    fn keeps_waiting_when_not_ready() {
+        let clock = ManualClock::new();
+        let done = status("Done");
+        let client = client(&clock, vec![&done, r#"{"error_code": 92}"#, &done, REPORT]);
+
+
+        let received = futures::executor::block_on(wait_for_report(&client, 5, PollOptions::new())).unwrap();
+
+
+        assert_eq!(received[0].phrase, "rust");
+        assert_eq!(clock.sleeps().len(), 1)
+    }
+
+    #[test]
+    fn failed_report() {
+        let clock = ManualClock::new();
+        let pending = status("Pending");
+        let failed = status("Failed");
+        let client = client(&clock, vec![&pending, &failed]);
+
+
+        let received = futures::executor::block_on(wait_for_report(&client, 5, PollOptions::new()));
+
+
+        assert!(matches!(received, Err(WordstatError::ReportFailed { report_id: 5 })))
+    }
+
+    #[test]
+    fn deadline() {
+        let clock = ManualClock::new();
+        let pending = status("Pending");
+        let client = client(&clock, vec![&pending, &pending, &pending]);
+        let options = PollOptions::new()
+            .with_interval(Duration::from_secs(4))
+            .with_timeout(Duration::from_secs(6));
+
+
+        let received = futures::executor::block_on(wait_for_report(&client, 5, options));
+
+
+        assert!(matches!(received, Err(WordstatError::DeadlineExceeded { report_id: 5 })));
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(4), Duration::from_secs(2)])
+    }
+
+    #[test]
+    fn cancellation() {
+        let clock = ManualClock::new();
+        let client = client(&clock, vec![]);
+        let token = CancellationToken::new();
+        token.cancel();
+
+
+        let received = futures::executor::block_on(wait_for_report(&client, 5, PollOptions::new().with_cancellation(token)));
+
+
+        assert!(matches!(received, Err(WordstatError::Cancelled)))
+    }
+
+    /// Cancels the token once a request is sent and never answers it
+    struct HangingTransport(CancellationToken);
+
+    #[async_trait::async_trait]
+    impl crate::transport::Transport for HangingTransport {
+        async fn post(&self, _url: &str, _payload: &Value) -> Result<Value, WordstatError> {
+            self.0.cancel();
+            future::pending().await
+        }
+    }
+
+    #[test]
+    fn cancels_requests_in_flight() {
+        let token = CancellationToken::new();
+        let client = Client::with_transport("token", "api_url", HangingTransport(token.clone()));
+        let options = PollOptions::new().with_cancellation(token);
+
+
+        let received = futures::executor::block_on(wait_for_report(&client, 5, options));
+
+
+        assert!(matches!(received, Err(WordstatError::Cancelled)))
+    }
+
+    #[test]
+    fn times_out_requests_in_flight() {
+        let mut client = Client::with_transport("token", "api_url", HangingTransport(CancellationToken::new()));
+        let clock = ManualClock::new();
+        client.set_clock(clock.clone());
+        let options = PollOptions::new().with_timeout(Duration::from_secs(60));
+
+
+        let received = futures::executor::block_on(wait_for_report(&client, 5, options));
+
+
+        assert!(matches!(received, Err(WordstatError::DeadlineExceeded { report_id: 5 })));
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(60)])
+    }
+}